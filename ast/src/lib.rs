@@ -1,23 +1,178 @@
 mod parse;
-pub use parse::parse;
+pub use parse::{parse, parse_tolerant, parse_program, parse_program_tolerant, parse_program_spanned, ParseError, TokenKind};
 
-use std::rc::Rc;
+mod pretty;
+pub use pretty::{Doc, pretty};
+
+use std::sync::Arc;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Expression {
     // just a variable
-    Var { name: Rc<str> },
+    Var { name: Arc<str> },
 
     // function application: f e
     App { f: Box<Expression>, e: Box<Expression> },
 
     // lambda abstraction: \x . e
-    Abs { name: Rc<str>, e: Box<Expression> },
+    Abs { name: Arc<str>, e: Box<Expression> },
 
     // let-in: let x = e0 in e1
-    Let { name: Rc<str>, e0: Box<Expression>, e1: Box<Expression> },
+    Let { name: Arc<str>, e0: Box<Expression>, e1: Box<Expression> },
 
     // booleans 
     True,
-    False
+    False,
+
+    // stands in for an expression that failed to parse
+    Error,
+}
+
+/// A range of byte offsets into the source, `start..end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn contains(&self, pos: usize) -> bool {
+        self.start <= pos && pos < self.end
+    }
+}
+
+/// Where a node came from in the source, along with the name it binds if it's a lambda, a `let`
+/// or a declaration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NodeSpan {
+    pub span: Span,
+    pub name: Option<Span>,
+}
+
+/// The source positions of a parsed program, kept apart from the AST so that the AST can still be
+/// compared and built by hand.
+///
+/// `nodes` holds every expression node of the program in post-order: the nodes of each `let`
+/// declaration's expression in the order the declarations appear, children before their parent,
+/// left to right. `decls` holds every declaration in the order they appear, with a module before
+/// the declarations inside it.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Spans {
+    pub nodes: Vec<NodeSpan>,
+    pub decls: Vec<NodeSpan>,
+}
+
+/// A top-level declaration of a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Declaration {
+    // let x = e
+    Let { name: Arc<str>, e: Expression },
+
+    // import Foo
+    Import { module: Arc<str> },
+
+    // module Foo = struct decls end
+    Module { name: Arc<str>, decls: Vec<Declaration> },
+}
+
+/// A whole program, which is a list of declarations where every declaration can use the ones
+/// before it.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Program {
+    pub decls: Vec<Declaration>,
+}
+
+impl Program {
+    /// The top-level `let` declarations of the program as (name, expression) pairs, in order.
+    /// Imports and modules are left out.
+    pub fn definitions(&self) -> Vec<(Arc<str>, Expression)> {
+        self.decls.iter()
+            .filter_map(|decl| match decl {
+                Declaration::Let { name, e } => Some((name.clone(), e.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl std::fmt::Display for Declaration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Declaration::Let { name, e } => write!(f, "let {name} = {e}"),
+            Declaration::Import { module } => write!(f, "import {module}"),
+            Declaration::Module { name, decls } => {
+                writeln!(f, "module {name} = struct")?;
+                for decl in decls {
+                    for line in decl.to_string().lines() {
+                        writeln!(f, "  {line}")?;
+                    }
+                }
+                write!(f, "end")
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Program {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for decl in &self.decls {
+            writeln!(f, "{decl}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Expression {
+    /// The number of nodes in the expression, which is how many entries it takes up in
+    /// [`Spans::nodes`].
+    pub fn size(&self) -> usize {
+        use Expression::*;
+        match self {
+            App { f, e } => f.size() + e.size() + 1,
+            Abs { e, .. } => e.size() + 1,
+            Let { e0, e1, .. } => e0.size() + e1.size() + 1,
+            Var { .. } | True | False | Error => 1,
+        }
+    }
+
+    /// The variables that the expression uses without binding them itself, in order of first use.
+    pub fn free_vars(&self) -> Vec<Arc<str>> {
+        fn go(e: &Expression, bound: &mut Vec<Arc<str>>, free: &mut Vec<Arc<str>>) {
+            use Expression::*;
+            match e {
+                Var { name } => {
+                    if !bound.contains(name) && !free.contains(name) {
+                        free.push(name.clone());
+                    }
+                }
+                App { f, e } => {
+                    go(f, bound, free);
+                    go(e, bound, free);
+                }
+                Abs { name, e } => {
+                    bound.push(name.clone());
+                    go(e, bound, free);
+                    bound.pop();
+                }
+                Let { name, e0, e1 } => {
+                    go(e0, bound, free);
+                    bound.push(name.clone());
+                    go(e1, bound, free);
+                    bound.pop();
+                }
+                True | False | Error => {},
+            }
+        }
+
+        let mut free = vec![];
+        go(self, &mut vec![], &mut free);
+        free
+    }
+}
+
+/// Prints the expression on a single line. Use [`pretty`] to fit it into a width instead.
+impl std::fmt::Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", pretty(self, usize::MAX))
+    }
 }
@@ -1,4 +1,4 @@
-use crate::Expression;
+use crate::{Declaration, Expression, NodeSpan, Program, Span, Spans};
 
 use thiserror::Error;
 
@@ -15,12 +15,17 @@ macro_rules! T {
 pub enum TokenKind {
     Let,
     In,
+    Import,
+    Module,
+    Struct,
+    End,
 
     BSlash,
     Eq,
     Dot,
     LParen,
     RParen,
+    SemiSemi,
 
     Id,
 
@@ -36,11 +41,16 @@ impl std::fmt::Display for TokenKind {
         match self {
             T![Let]    => write!(f, r"let"),
             T![In]     => write!(f, r"in"),
+            T![Import] => write!(f, "import"),
+            T![Module] => write!(f, "module"),
+            T![Struct] => write!(f, "struct"),
+            T![End]    => write!(f, "end"),
             T![BSlash] => write!(f, r"\"),
             T![Eq]     => write!(f, "="),
             T![Dot]    => write!(f, "."),
             T![LParen] => write!(f, "("),
             T![RParen] => write!(f, ")"),
+            T![SemiSemi] => write!(f, ";;"),
             T![Id]     => write!(f, "ID"),
             T![True]   => write!(f, "true"),
             T![False]  => write!(f, "false"),
@@ -50,6 +60,35 @@ impl std::fmt::Display for TokenKind {
     }
 }
 
+// The inverse of Display, so that errors mentioning tokens can be read back
+impl std::str::FromStr for TokenKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TokenKind, String> {
+        let kind = match s {
+            r"let"   => T![Let],
+            r"in"    => T![In],
+            "import" => T![Import],
+            "module" => T![Module],
+            "struct" => T![Struct],
+            "end"    => T![End],
+            r"\"     => T![BSlash],
+            "="      => T![Eq],
+            "."      => T![Dot],
+            "("      => T![LParen],
+            ")"      => T![RParen],
+            ";;"     => T![SemiSemi],
+            "ID"     => T![Id],
+            "true"   => T![True],
+            "false"  => T![False],
+            "End of File" => T![EOF],
+            "Error"  => T![Error],
+            _ => return Err(format!("'{s}' isn't a token kind")),
+        };
+        Ok(kind)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Token {
     kind: TokenKind,
@@ -69,7 +108,8 @@ impl Default for Token {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Error)]
+/// A syntax error, where `pos` is the byte offset into the source that it was found at.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
 pub enum ParseError {
     #[error("Unexpected token '{unexpected}' found at position {pos}, expecting {}.",
         expected.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(", ")
@@ -86,11 +126,25 @@ pub enum ParseError {
     },
 }
 
+impl ParseError {
+    fn pos(&self) -> usize {
+        match self {
+            ParseError::UnexpectedToken { pos, .. } | ParseError::InvalidToken { pos, .. } => *pos,
+        }
+    }
+}
+
 struct ParseContext<'src> {
     src: &'src str,
     pos: usize,
     chr: Chars<'src>,
     buf: [Token; 4],
+    errors: Vec<ParseError>,
+    // whether a `let` at the start of a line begins a new declaration
+    program: bool,
+    spans: Spans,
+    // where the last token taken with next() ends
+    last_end: usize,
 }
 
 impl ParseContext<'_> {
@@ -98,7 +152,10 @@ impl ParseContext<'_> {
         let pos = 0;
         let chr = src.chars();
         let buf = [Token::default(); 4];
-        let mut ctx = ParseContext { src, pos, chr, buf };
+        let errors = vec![];
+        let program = false;
+        let spans = Spans::default();
+        let mut ctx = ParseContext { src, pos, chr, buf, errors, program, spans, last_end: 0 };
 
         for _ in 0..buf.len() { ctx.next(); }
         ctx
@@ -114,10 +171,55 @@ impl ParseContext<'_> {
         else { Ok(self.next()) }
     }
 
+    // Tokens that can end an expression, which the parser skips ahead to after an error.
+    fn is_sync(&self, tok: Token) -> bool {
+        matches!(tok.kind, T![EOF] | T![RParen] | T![In] | T![SemiSemi] | T![End] | T![Import] | T![Module])
+            || self.starts_decl(tok)
+    }
+
+    // In a program, declarations can be separated by a newline instead of `;;`, in which case the
+    // next one starts with a `let`, `import` or `module` at the start of a line.
+    fn starts_decl(&self, tok: Token) -> bool {
+        let before = self.src[..tok.pos].trim_end_matches([' ', '\t', '\r']);
+        self.program
+            && matches!(tok.kind, T![Let] | T![Import] | T![Module])
+            && (before.is_empty() || before.ends_with('\n'))
+    }
+
+    // Records an error, unless the last one was found at the same position. Recovery stops at a
+    // token that can end an expression, and the rules around it would report that token again when
+    // it isn't what they expect either, so each sync point only gets one error.
+    fn error(&mut self, err: ParseError) {
+        if self.errors.last().is_some_and(|last| last.pos() == err.pos()) { return; }
+        self.errors.push(err);
+    }
+
+    fn recover(&mut self, err: ParseError) -> Expression {
+        let start = err.pos();
+        self.error(err);
+        while !self.is_sync(self.peek_nth(0)) {
+            self.next();
+        }
+        self.node(start, None, Expression::Error)
+    }
+
+    // Records the span of a node that starts at 'start' and ends with the last token taken. This
+    // has to be called exactly once for every node, after its children, to keep spans.nodes in
+    // post-order.
+    fn node(&mut self, start: usize, name: Option<Token>, e: Expression) -> Expression {
+        let span = Span { start, end: self.last_end.max(start) };
+        let name = name.map(|tok| Span { start: tok.pos, end: tok.pos + tok.len });
+        self.spans.nodes.push(NodeSpan { span, name });
+        e
+    }
+
     fn next(&mut self) -> Token {
         let mut tok = self.lex();
         mem::swap(&mut tok, &mut self.buf[0]);
         self.buf.rotate_left(1);
+        if tok.kind != T![EOF] {
+            self.last_end = tok.pos + tok.len;
+        }
         tok
     }
 
@@ -145,18 +247,41 @@ impl ParseContext<'_> {
                 '.'  => T![Dot],
                 '('  => T![LParen],
                 ')'  => T![RParen],
+                ';' if self.peek_char() == Some(';') => {
+                    self.next_char();
+                    T![SemiSemi]
+                }
 
+                // A capitalized name directly followed by a dot and another name is a qualified
+                // name like `Foo.bar`, which is a single Id. This means `\X.x` has to be written
+                // as `\X. x`.
                 c if c.is_alphabetic() => {
                     let mut s = String::from(c);
                     let valid_char = |c: char| c.is_alphabetic() || c == '\'' || c == '_';
-                    while let Some(c_) = self.peek_char() && valid_char(c_) {
-                        s.push(c_);
+                    let mut segment = 0;
+                    loop {
+                        while let Some(c_) = self.peek_char() && valid_char(c_) {
+                            s.push(c_);
+                            self.next_char();
+                        }
+
+                        let mut ahead = self.chr.clone();
+                        let is_module = s[segment..].starts_with(char::is_uppercase);
+                        let qualifies = ahead.next() == Some('.')
+                            && ahead.next().is_some_and(char::is_alphabetic);
+                        if !(is_module && qualifies) { break; }
                         self.next_char();
+                        s.push('.');
+                        segment = s.len();
                     }
 
                     let kind = match s.as_str() {
                         "let"   => T![Let],
                         "in"    => T![In],
+                        "import" => T![Import],
+                        "module" => T![Module],
+                        "struct" => T![Struct],
+                        "end"   => T![End],
                         "true"  => T![True],
                         "false" => T![False],
                         _ => T![Id],
@@ -179,10 +304,11 @@ impl ParseContext<'_> {
         }
     }
 
+    // positions are byte offsets, so that lexemes can be sliced out of the source
     #[inline]
     fn next_char(&mut self) -> Option<char> {
         let c = self.chr.next();
-        self.pos += if c.is_some() { 1 } else { 0 };
+        self.pos += c.map_or(0, char::len_utf8);
         c
     }
 
@@ -194,54 +320,243 @@ impl ParseContext<'_> {
 }
 
 pub fn parse(src: &str) -> Result<Expression, ParseError> {
+    let (e, errors) = parse_tolerant(src);
+    match errors.into_iter().next() {
+        Some(err) => Err(err),
+        None => Ok(e),
+    }
+}
+
+/// Parses `src` while collecting every syntax error found along the way. Parts of the source that
+/// couldn't be parsed are replaced by [`Expression::Error`] so that the rest of the expression can
+/// still be used, e.g. for type checking.
+pub fn parse_tolerant(src: &str) -> (Expression, Vec<ParseError>) {
     let mut ctx = ParseContext::new(src);
-    parse_expr(&mut ctx, 0)
+    let e = parse_expr(&mut ctx, 0);
+
+    // Anything left over is a stray `in` or `)`. Keep parsing whatever comes after it so its
+    // errors get reported too, even though the result can't be part of `e`.
+    loop {
+        let tok = ctx.peek_nth(0);
+        if tok.kind == T![EOF] { break; }
+        ctx.error(ParseError::UnexpectedToken {
+            unexpected: tok.kind,
+            expected: vec![T![EOF]],
+            pos: tok.pos,
+        });
+        ctx.next();
+        // a stray token right after is reported on the next round, and there's nothing to parse
+        // at the end
+        if ctx.is_sync(ctx.peek_nth(0)) { continue; }
+        let nodes = ctx.spans.nodes.len();
+        parse_expr(&mut ctx, 0);
+        ctx.spans.nodes.truncate(nodes);
+    }
+
+    (e, ctx.errors)
+}
+
+pub fn parse_program(src: &str) -> Result<Program, ParseError> {
+    let (program, errors) = parse_program_tolerant(src);
+    match errors.into_iter().next() {
+        Some(err) => Err(err),
+        None => Ok(program),
+    }
+}
+
+/// Parses a program made of declarations, which are separated by `;;` or by starting the next
+/// declaration on a new line. A declaration is one of
+///
+/// - `let name = e`
+/// - `import Foo`, which brings the declarations of module `Foo` into scope as `Foo.name`
+/// - `module Foo = struct declarations end`
+///
+/// Like [`parse_tolerant`], every syntax error is collected and parsing carries on with the next
+/// declaration.
+pub fn parse_program_tolerant(src: &str) -> (Program, Vec<ParseError>) {
+    let (program, _, errors) = parse_program_spanned(src);
+    (program, errors)
+}
+
+/// Like [`parse_program_tolerant`], but also returns where every node and declaration of the
+/// program is in the source, see [`Spans`].
+pub fn parse_program_spanned(src: &str) -> (Program, Spans, Vec<ParseError>) {
+    let mut ctx = ParseContext::new(src);
+    ctx.program = true;
+    let decls = parse_decls(&mut ctx, false);
+    (Program { decls }, ctx.spans, ctx.errors)
+}
+
+// Parses declarations up to the end of the file, or up to the `end` of the module if 'nested'.
+fn parse_decls(ctx: &mut ParseContext, nested: bool) -> Vec<Declaration> {
+    let mut decls = vec![];
+    loop {
+        while ctx.peek_nth(0).kind == T![SemiSemi] { ctx.next(); }
+
+        let tok = ctx.peek_nth(0);
+        match tok.kind {
+            T![EOF] => break,
+            T![End] if nested => break,
+            T![Let] | T![Import] | T![Module] => {},
+            _ => {
+                ctx.error(ParseError::UnexpectedToken {
+                    unexpected: tok.kind,
+                    expected: vec![T![Let], T![Import], T![Module]],
+                    pos: tok.pos,
+                });
+                ctx.next();
+                skip_decl(ctx);
+                continue;
+            }
+        }
+
+        match parse_decl(ctx) {
+            Ok(decl) => decls.push(decl),
+            Err(err) => {
+                ctx.error(err);
+                skip_decl(ctx);
+                continue;
+            }
+        }
+
+        let tok = ctx.peek_nth(0);
+        if !matches!(tok.kind, T![SemiSemi] | T![EOF] | T![End]) && !ctx.starts_decl(tok) {
+            ctx.error(ParseError::UnexpectedToken {
+                unexpected: tok.kind,
+                expected: vec![T![SemiSemi], T![EOF]],
+                pos: tok.pos,
+            });
+            skip_decl(ctx);
+        }
+    }
+    decls
+}
+
+fn parse_decl(ctx: &mut ParseContext) -> Result<Declaration, ParseError> {
+    let tok = ctx.next();
+    let name_tok = ctx.expect(T![Id])?;
+    let name = ctx.lexeme(name_tok).into();
+    let name_span = Span { start: name_tok.pos, end: name_tok.pos + name_tok.len };
+
+    // a module's span goes before the spans of the declarations inside it
+    let i = ctx.spans.decls.len();
+    let decl = match tok.kind {
+        T![Let] => {
+            let e = match ctx.expect(T![Eq]) {
+                Ok(_)    => parse_expr(ctx, 0),
+                Err(err) => ctx.recover(err),
+            };
+            ctx.spans.decls.insert(i, NodeSpan::default());
+            Declaration::Let { name, e }
+        }
+
+        T![Import] => {
+            ctx.spans.decls.insert(i, NodeSpan::default());
+            Declaration::Import { module: name }
+        }
+
+        T![Module] => {
+            ctx.expect(T![Eq])?;
+            ctx.expect(T![Struct])?;
+            ctx.spans.decls.insert(i, NodeSpan::default());
+            let decls = parse_decls(ctx, true);
+            // a missing `end` only loses the `end`, not the whole module
+            if let Err(err) = ctx.expect(T![End]) {
+                ctx.error(err);
+            }
+            Declaration::Module { name, decls }
+        }
+
+        _ => unreachable!("parse_decls only calls parse_decl on the start of a declaration"),
+    };
+
+    let span = Span { start: tok.pos, end: ctx.last_end };
+    ctx.spans.decls[i] = NodeSpan { span, name: Some(name_span) };
+    Ok(decl)
+}
+
+// Skips ahead to where the next declaration could start
+fn skip_decl(ctx: &mut ParseContext) {
+    loop {
+        let tok = ctx.peek_nth(0);
+        if matches!(tok.kind, T![SemiSemi] | T![EOF] | T![End]) || ctx.starts_decl(tok) { break; }
+        ctx.next();
+    }
 }
 
 // Basically just a Pratt Parser, except there's really only one "operator" which is the
 // left-associative application
 // https://matklad.github.io/2020/04/13/simple-but-powerful-pratt-parsing.html
-fn parse_expr(ctx: &mut ParseContext, min_bp: u8) -> Result<Expression, ParseError> {
+//
+// Errors are recorded in the context instead of being returned. After an error the parser skips
+// ahead to the next `in`, `)` or EOF, which are the tokens that can end an expression, and puts an
+// `Expression::Error` where the broken expression was.
+fn parse_expr(ctx: &mut ParseContext, min_bp: u8) -> Expression {
+    // a `let` at the start of a line is only the next declaration if it can't start this expression
+    let tok = ctx.peek_nth(0);
+    if ctx.is_sync(tok) && tok.kind != T![Let] {
+        return ctx.recover(ParseError::UnexpectedToken {
+            unexpected: tok.kind,
+            expected: vec![T![LParen], T![Id], T![BSlash], T![Let], T![True], T![False]],
+            pos: tok.pos
+        });
+    }
+
     let tok = ctx.next();
+    let start = tok.pos;
     let mut lhs = match tok.kind {
         T![LParen] => {
-            let e = parse_expr(ctx, 0)?;
-            ctx.expect(T![RParen])?;
+            let e = parse_expr(ctx, 0);
+            if let Err(err) = ctx.expect(T![RParen]) {
+                ctx.error(err);
+            }
             e
         }
 
         T![Id] => {
             let name = ctx.lexeme(tok).into();
-            Expression::Var { name }
+            ctx.node(tok.pos, None, Expression::Var { name })
         }
 
         T![BSlash] => {
-            let name = ctx.expect(T![Id])?;
-            let name = ctx.lexeme(name).into();
-            ctx.expect(T![Dot])?;
-            let e = parse_expr(ctx, 0)?.into();
-            Expression::Abs { name, e }
+            let name_tok = match ctx.expect(T![Id]) {
+                Ok(name) => name,
+                Err(err) => return ctx.recover(err),
+            };
+            let name = ctx.lexeme(name_tok).into();
+            let e = match ctx.expect(T![Dot]) {
+                Ok(_)    => parse_expr(ctx, 0).into(),
+                Err(err) => ctx.recover(err).into(),
+            };
+            ctx.node(tok.pos, Some(name_tok), Expression::Abs { name, e })
         }
 
         T![Let] => {
-            let name = ctx.expect(T![Id])?;
-            let name = ctx.lexeme(name).into();
-            ctx.expect(T![Eq])?;
-            let e0 = parse_expr(ctx, 0)?.into();
-            ctx.expect(T![In])?;
-            let e1 = parse_expr(ctx, 0)?.into();
-            Expression::Let { name, e0, e1 }
+            let name_tok = match ctx.expect(T![Id]) {
+                Ok(name) => name,
+                Err(err) => return ctx.recover(err),
+            };
+            let name = ctx.lexeme(name_tok).into();
+            let e0 = match ctx.expect(T![Eq]) {
+                Ok(_)    => parse_expr(ctx, 0).into(),
+                Err(err) => ctx.recover(err).into(),
+            };
+            let e1 = match ctx.expect(T![In]) {
+                Ok(_)    => parse_expr(ctx, 0).into(),
+                Err(err) => ctx.recover(err).into(),
+            };
+            ctx.node(tok.pos, Some(name_tok), Expression::Let { name, e0, e1 })
         }
 
-        T![True]  => Expression::True,
-        T![False] => Expression::False,
+        T![True]  => ctx.node(tok.pos, None, Expression::True),
+        T![False] => ctx.node(tok.pos, None, Expression::False),
 
-        T![Error] => return Err(ParseError::InvalidToken {
+        T![Error] => return ctx.recover(ParseError::InvalidToken {
             lexeme: ctx.lexeme(tok).to_string(),
             pos: tok.pos,
         }),
 
-        _ => return Err(ParseError::UnexpectedToken {
+        _ => return ctx.recover(ParseError::UnexpectedToken {
             unexpected: tok.kind,
             expected: vec![T![LParen], T![Id], T![BSlash], T![Let], T![True], T![False]],
             pos: tok.pos
@@ -250,21 +565,23 @@ fn parse_expr(ctx: &mut ParseContext, min_bp: u8) -> Result<Expression, ParseErr
 
     loop {
         let tok = ctx.peek_nth(0);
-        if matches!(tok.kind, T![EOF] | T![RParen] | T![In]) { break; }
+        if ctx.is_sync(tok) { break; }
         if tok.kind == T![Error] {
-            return Err(ParseError::InvalidToken {
+            ctx.error(ParseError::InvalidToken {
                 lexeme: ctx.lexeme(tok).to_string(),
                 pos: tok.pos,
-            })
+            });
+            ctx.next();
+            continue;
         }
         let (l_bp, r_bp) = (1, 2); // binding power of App
         if l_bp < min_bp { break; }
 
-        let rhs = parse_expr(ctx, r_bp)?;
-        lhs = Expression::App { f: lhs.into(), e: rhs.into() };
+        let rhs = parse_expr(ctx, r_bp);
+        lhs = ctx.node(start, None, Expression::App { f: lhs.into(), e: rhs.into() });
     }
-    
-    Ok(lhs)
+
+    lhs
 }
 
 #[cfg(test)]
@@ -332,4 +649,153 @@ mod tests {
             
         assert_eq!(e_parse, Ok(e_correct))
     }
+
+    #[test]
+    fn test_program() {
+        let src = "let id = \\x. x\nlet a =\n  let b = true in\n  id b;; let c = id a\n";
+        let (program, errors) = parse_program_tolerant(src);
+        let var = |name: &str| Expression::Var { name: name.into() };
+        let decls = vec![
+            Declaration::Let {
+                name: "id".into(),
+                e: Expression::Abs { name: "x".into(), e: var("x").into() },
+            },
+            Declaration::Let {
+                name: "a".into(),
+                e: Expression::Let {
+                    name: "b".into(),
+                    e0: Expression::True.into(),
+                    e1: Expression::App { f: var("id").into(), e: var("b").into() }.into(),
+                },
+            },
+            Declaration::Let {
+                name: "c".into(),
+                e: Expression::App { f: var("id").into(), e: var("a").into() },
+            },
+        ];
+
+        assert_eq!(errors, vec![]);
+        assert_eq!(program, Program { decls });
+    }
+
+    #[test]
+    fn test_modules() {
+        let src = "import List\nmodule M = struct\n  let id = \\x. x\n  module N = struct let t = true end\nend\nlet a = M.N.t;; let b = \\X. X";
+        let (program, errors) = parse_program_tolerant(src);
+        let var = |name: &str| Expression::Var { name: name.into() };
+        let decls = vec![
+            Declaration::Import { module: "List".into() },
+            Declaration::Module {
+                name: "M".into(),
+                decls: vec![
+                    Declaration::Let {
+                        name: "id".into(),
+                        e: Expression::Abs { name: "x".into(), e: var("x").into() },
+                    },
+                    Declaration::Module {
+                        name: "N".into(),
+                        decls: vec![Declaration::Let { name: "t".into(), e: Expression::True }],
+                    },
+                ],
+            },
+            Declaration::Let { name: "a".into(), e: var("M.N.t") },
+            Declaration::Let {
+                name: "b".into(),
+                e: Expression::Abs { name: "X".into(), e: var("X").into() },
+            },
+        ];
+
+        assert_eq!(errors, vec![]);
+        assert_eq!(program, Program { decls });
+        assert_eq!(parse_program(&program.to_string()), Ok(program));
+    }
+
+    #[test]
+    fn test_qualified_names() {
+        let mut ctx = ParseContext::new(r"Foo.Bar.baz x.y X.(");
+        assert_eq!(ctx.next(), Token::new(T![Id], 0, 11));
+        assert_eq!(ctx.next(), Token::new(T![Id], 12, 1));
+        assert_eq!(ctx.next(), Token::new(T![Dot], 13, 1));
+        assert_eq!(ctx.next(), Token::new(T![Id], 14, 1));
+        assert_eq!(ctx.next(), Token::new(T![Id], 16, 1));
+        assert_eq!(ctx.next(), Token::new(T![Dot], 17, 1));
+
+        // a capitalized binder directly followed by its body reads as a
+        // qualified name, so it needs a space after the dot
+        let mut ctx = ParseContext::new(r"\X.x");
+        assert_eq!(ctx.next(), Token::new(T![BSlash], 0, 1));
+        assert_eq!(ctx.next(), Token::new(T![Id], 1, 3));
+        assert!(parse(r"\X.x").is_err());
+        let e = Expression::Abs { name: "X".into(), e: Expression::Var { name: "x".into() }.into() };
+        assert_eq!(parse(r"\X. x"), Ok(e));
+        assert!(parse(r"\x.x").is_ok());
+    }
+
+    #[test]
+    fn test_spans() {
+        let src = "let f = \\x. (x) true\nmodule M = struct let y = let z = é in z end";
+        let (program, spans, errors) = parse_program_spanned(src);
+        assert_eq!(errors, vec![]);
+
+        let span = |start, end| Span { start, end };
+        let nodes = spans.nodes.iter().map(|n| (&src[n.span.start..n.span.end], n.name.map(|s| &src[s.start..s.end])));
+        assert_eq!(nodes.collect::<Vec<_>>(), [
+            ("x", None),
+            ("true", None),
+            ("(x) true", None),
+            ("\\x. (x) true", Some("x")),
+            ("é", None),
+            ("z", None),
+            ("let z = é in z", Some("z")),
+        ]);
+        assert_eq!(spans.decls, [
+            NodeSpan { span: span(0, 20), name: Some(span(4, 5)) },
+            NodeSpan { span: span(21, 66), name: Some(span(28, 29)) },
+            NodeSpan { span: span(39, 62), name: Some(span(43, 44)) },
+        ]);
+        assert_eq!(&src[39..62], "let y = let z = é in z");
+        let sizes = program.definitions().iter().map(|(_, e)| e.size()).sum::<usize>();
+        assert_eq!(sizes + 3, spans.nodes.len());
+    }
+
+    #[test]
+    fn test_recovery() {
+        let (e_parse, errors) = parse_tolerant(r"let x = (\. x) in x - (let y = in y)");
+        let e_correct = Expression::Let {
+            name: "x".into(),
+            e0: Expression::Error.into(),
+            e1: Expression::App {
+                f: Expression::Var { name: "x".into() }.into(),
+                e: Expression::Let {
+                    name: "y".into(),
+                    e0: Expression::Error.into(),
+                    e1: Expression::Var { name: "y".into() }.into(),
+                }.into(),
+            }.into(),
+        };
+
+        assert_eq!(e_parse, e_correct);
+        assert_eq!(errors, vec![
+            ParseError::UnexpectedToken { unexpected: T![Dot], expected: vec![T![Id]], pos: 10 },
+            ParseError::InvalidToken { lexeme: "-".to_string(), pos: 20 },
+            ParseError::UnexpectedToken {
+                unexpected: T![In],
+                expected: vec![T![LParen], T![Id], T![BSlash], T![Let], T![True], T![False]],
+                pos: 31,
+            },
+        ]);
+
+        // one mistake is reported once, even when the rules around it stop at the same token
+        let expr_start = vec![T![LParen], T![Id], T![BSlash], T![Let], T![True], T![False]];
+        assert_eq!(parse_tolerant("let x = ) in x").1, vec![
+            ParseError::UnexpectedToken { unexpected: T![RParen], expected: expr_start, pos: 8 },
+            ParseError::UnexpectedToken { unexpected: T![In], expected: vec![T![EOF]], pos: 10 },
+        ]);
+        assert_eq!(parse_tolerant("(x in y").1, vec![
+            ParseError::UnexpectedToken { unexpected: T![In], expected: vec![T![RParen]], pos: 3 },
+        ]);
+        assert_eq!(parse_tolerant("x)").1, vec![
+            ParseError::UnexpectedToken { unexpected: T![RParen], expected: vec![T![EOF]], pos: 1 },
+        ]);
+    }
 }
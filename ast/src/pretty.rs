@@ -0,0 +1,246 @@
+use crate::Expression;
+
+use std::ops::Add;
+use std::rc::Rc;
+
+/// A document in the style of Wadler's "A prettier printer". A document describes every way some
+/// text can be laid out, and [`Doc::render`] picks the layout that fits into a given width.
+#[derive(Debug, Clone)]
+pub enum Doc {
+    Nil,
+    Text(Rc<str>),
+    // a space if the enclosing group fits on one line, otherwise a newline
+    Line,
+    Nest(usize, Rc<Doc>),
+    Concat(Rc<Doc>, Rc<Doc>),
+    // lays out its contents on one line if they fit
+    Group(Rc<Doc>),
+}
+
+impl Doc {
+    pub fn text(s: impl Into<Rc<str>>) -> Doc {
+        Doc::Text(s.into())
+    }
+
+    pub fn nest(self, indent: usize) -> Doc {
+        Doc::Nest(indent, self.into())
+    }
+
+    pub fn group(self) -> Doc {
+        Doc::Group(self.into())
+    }
+
+    pub fn render(&self, width: usize) -> String {
+        let mut out = String::new();
+        let mut col = 0;
+        let mut stack = vec![(0, false, self)];
+
+        while let Some((indent, flat, doc)) = stack.pop() {
+            match doc {
+                Doc::Nil => {},
+                Doc::Text(s) => {
+                    out.push_str(s);
+                    col += s.chars().count();
+                }
+                Doc::Line if flat => {
+                    out.push(' ');
+                    col += 1;
+                }
+                Doc::Line => {
+                    out.push('\n');
+                    out.extend(std::iter::repeat_n(' ', indent));
+                    col = indent;
+                }
+                Doc::Nest(i, doc) => stack.push((indent + i, flat, doc)),
+                Doc::Concat(a, b) => {
+                    stack.push((indent, flat, b));
+                    stack.push((indent, flat, a));
+                }
+                Doc::Group(doc) => {
+                    let flat = flat || fits(width.saturating_sub(col), doc, &stack);
+                    stack.push((indent, flat, doc));
+                }
+            }
+        }
+
+        out
+    }
+}
+
+/* Whether 'doc' laid out on one line, followed by everything up to the next
+ * newline in 'rest', fits into 'width' columns */
+fn fits(width: usize, doc: &Doc, rest: &[(usize, bool, &Doc)]) -> bool {
+    let mut width = isize::try_from(width).unwrap_or(isize::MAX);
+    let mut stack = vec![(true, doc)];
+    let mut rest = rest.iter().rev();
+
+    loop {
+        if width < 0 { return false; }
+        let (flat, doc) = match stack.pop() {
+            Some(item) => item,
+            None => match rest.next() {
+                Some((_, flat, doc)) => (*flat, *doc),
+                None => return true,
+            }
+        };
+
+        match doc {
+            Doc::Nil => {},
+            Doc::Text(s) => width -= s.chars().count() as isize,
+            Doc::Line if flat => width -= 1,
+            Doc::Line => return true,
+            Doc::Nest(_, doc) | Doc::Group(doc) => stack.push((flat, doc)),
+            Doc::Concat(a, b) => {
+                stack.push((flat, b));
+                stack.push((flat, a));
+            }
+        }
+    }
+}
+
+impl Add for Doc {
+    type Output = Doc;
+
+    fn add(self, rhs: Doc) -> Doc {
+        Doc::Concat(self.into(), rhs.into())
+    }
+}
+
+/// Prints `e` as source code that parses back into `e`, using as few parentheses as possible and
+/// breaking lines so that the output fits into `width` columns where it can.
+pub fn pretty(e: &Expression, width: usize) -> String {
+    to_doc(e, true).render(width)
+}
+
+fn parens(doc: Doc) -> Doc {
+    Doc::text("(") + doc.nest(1) + Doc::text(")")
+}
+
+// Lambdas and lets extend as far to the right as they can, so they only go without parentheses
+// when nothing comes after them, i.e. when they're `last`.
+fn to_doc(e: &Expression, last: bool) -> Doc {
+    use Expression::*;
+    match e {
+        Var { name } => Doc::text(&**name),
+        True  => Doc::text("true"),
+        False => Doc::text("false"),
+        Error => Doc::text("<error>"),
+
+        App { .. } => {
+            let mut args = vec![];
+            let mut f = e;
+            while let App { f: f_, e } = f {
+                args.push(&**e);
+                f = f_;
+            }
+            args.reverse();
+
+            let f = match f {
+                Abs { .. } | Let { .. } => parens(to_doc(f, true)),
+                f => to_doc(f, false),
+            };
+
+            let n = args.len();
+            let args = args.into_iter()
+                .enumerate()
+                .fold(Doc::Nil, |doc, (i, e)| {
+                    let e = match e {
+                        App { .. } => parens(to_doc(e, true)),
+                        Abs { .. } | Let { .. } if !last || i + 1 < n => parens(to_doc(e, true)),
+                        e => to_doc(e, last),
+                    };
+                    doc + Doc::Line + e
+                });
+
+            (f + args.nest(2)).group()
+        }
+
+        Abs { name, e } => {
+            let head = Doc::text(format!(r"\{name}."));
+            (head + (Doc::Line + to_doc(e, true)).nest(2)).group()
+        }
+
+        Let { name, e0, e1 } => {
+            let head = Doc::text(format!("let {name} ="));
+            let binding = (head + (Doc::Line + to_doc(e0, true)).nest(2) + Doc::Line + Doc::text("in")).group();
+            (binding + Doc::Line + to_doc(e1, true)).group()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    // xorshift, so that the tests don't need a dependency just for random numbers
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self, n: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % n
+        }
+    }
+
+    fn gen_expr(rng: &mut Rng, depth: u32) -> Expression {
+        let names = ["x", "y", "f", "long_name", "x'"];
+        let name = |rng: &mut Rng| names[rng.next(names.len() as u64) as usize].into();
+
+        let leaf = depth == 0 || rng.next(4) == 0;
+        match if leaf { rng.next(3) } else { 3 + rng.next(3) } {
+            0 => Expression::Var { name: name(rng) },
+            1 => Expression::True,
+            2 => Expression::False,
+            3 => Expression::App {
+                f: gen_expr(rng, depth - 1).into(),
+                e: gen_expr(rng, depth - 1).into(),
+            },
+            4 => Expression::Abs { name: name(rng), e: gen_expr(rng, depth - 1).into() },
+            _ => Expression::Let {
+                name: name(rng),
+                e0: gen_expr(rng, depth - 1).into(),
+                e1: gen_expr(rng, depth - 1).into(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut rng = Rng(0x2545F4914F6CDD1D);
+        for _ in 0..1000 {
+            let e = gen_expr(&mut rng, 6);
+            for width in [0, 20, 80, usize::MAX] {
+                let src = pretty(&e, width);
+                assert_eq!(parse(&src), Ok(e.clone()), "printed as:\n{src}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_minimal_parens() {
+        let e = parse(r"(\f. f) (\x. (x y) (let z = x in z)) (f \y. y)").unwrap();
+        assert_eq!(pretty(&e, usize::MAX), r"(\f. f) (\x. x y let z = x in z) (f \y. y)");
+    }
+
+    #[test]
+    fn test_layout() {
+        let e = parse(r"let compose = \f. \g. \x. f (g x) in compose compose compose").unwrap();
+        assert_eq!(pretty(&e, 30), [
+            r"let compose =",
+            r"  \f. \g. \x. f (g x)",
+            r"in",
+            r"compose compose compose",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn test_display() {
+        // a format width doesn't make it break lines
+        let e = parse(r"let compose = \f. \g. \x. f (g x) in compose compose compose").unwrap();
+        assert_eq!(format!("{e:10}"), pretty(&e, usize::MAX));
+        assert!(!e.to_string().contains('\n'));
+    }
+}
@@ -0,0 +1,35 @@
+//! A language server for the lambda calculus, speaking LSP over stdin and stdout.
+
+use algorithm_j::lsp::{self, Server};
+
+use std::io;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let mut input = io::stdin().lock();
+    let mut output = io::stdout().lock();
+    let mut server = Server::new();
+
+    loop {
+        let body = match lsp::read_message(&mut input) {
+            Ok(Some(body)) => body,
+            // the client went away without asking us to exit
+            Ok(None) => return ExitCode::FAILURE,
+            Err(err) => {
+                eprintln!("{err}");
+                return ExitCode::FAILURE;
+            }
+        };
+
+        for msg in server.handle(&body) {
+            if let Err(err) = lsp::write_message(&mut output, &msg) {
+                eprintln!("{err}");
+                return ExitCode::FAILURE;
+            }
+        }
+
+        if let Some(code) = server.exit_code() {
+            return ExitCode::from(code as u8);
+        }
+    }
+}
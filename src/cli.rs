@@ -0,0 +1,358 @@
+/*  The non-interactive side of the binary in main.rs, for build scripts:
+ *
+ *      algorithm-j check [--format human|json] [-I dir]... [file]
+ *      algorithm-j infer [--format human|json] [-I dir]... [file]
+ *      algorithm-j eval [--format human|json] [-I dir]... [--vm] [file]
+ *      algorithm-j js [--format human|json] [-I dir]... [file]
+ *
+ *  All of them read the program from stdin when there's no file or it is '-'.
+ *  The exit code is 0 when the program has no errors, 1 when it has some and
+ *  2 when the arguments are wrong or the file can't be read. Programs with
+ *  errors aren't evaluated. With --vm, eval compiles the program to bytecode
+ *  and runs it on the vm module's machine instead of interpreting it. js
+ *  prints the program compiled to JavaScript by the js module.
+ *
+ *  In the human format errors go to stderr as 'file:line:column: message',
+ *  like compilers print them. In the JSON format everything goes to stdout as
+ *  one object, with the errors serialized like the ser module does. */
+
+use crate::eval;
+use crate::js;
+use crate::vm;
+use crate::module::ModuleLoader;
+use crate::ser::{Json, Serialize};
+use crate::typck::{self, TypeError, TypedProgram};
+
+use ast::{Declaration, ParseError, Program, Span};
+use thiserror::Error;
+
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// How the binary is used, printed for --help and after usage errors.
+pub const USAGE: &str = "\
+Usage: algorithm-j                          start the REPL
+       algorithm-j check [options] [file]   check a program, the exit code tells whether it has errors
+       algorithm-j infer [options] [file]   print the type of every declaration of a program
+       algorithm-j eval [options] [file]    print the value of every declaration of a program
+       algorithm-j js [options] [file]      print a program compiled to JavaScript, for node to run
+
+The program is read from stdin if there's no file or it is '-'.
+
+Options:
+  --format human|json   how to print types and errors, human by default
+  -I, --include <dir>   also look for imported modules in <dir>, after the program's own directory
+  --vm                  run eval on the bytecode machine rather than the interpreter
+  -h, --help            print this";
+
+/// The exit code for programs with errors.
+pub const EXIT_ERRORS: u8 = 1;
+
+/// The exit code for wrong arguments and unreadable files.
+pub const EXIT_USAGE: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Human,
+    Json,
+}
+
+/// What the binary was asked to do.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Repl,
+    Help,
+    Check(Options),
+    Infer(Options),
+    Eval(Options),
+    Js(Options),
+}
+
+/// The options shared by the subcommands.
+#[derive(Debug, PartialEq, Eq, Default)]
+pub struct Options {
+    /// The program to read, or None for stdin
+    pub file: Option<PathBuf>,
+    pub format: Format,
+    pub include: Vec<PathBuf>,
+    /// Whether eval runs the program on the bytecode machine
+    pub vm: bool,
+}
+
+/// Arguments that don't make sense.
+#[derive(Debug, PartialEq, Eq, Error)]
+pub enum UsageError {
+    #[error("Unknown command '{0}'.")]
+    UnknownCommand(String),
+    #[error("Unknown option '{0}'.")]
+    UnknownOption(String),
+    #[error("Option '{0}' expects a value.")]
+    MissingValue(String),
+    #[error("Unknown format '{0}', expecting human or json.")]
+    UnknownFormat(String),
+    #[error("Unexpected argument '{0}', only one file can be given.")]
+    ExtraArgument(String),
+    #[error("Option '{option}' only works with the {expected} command, not {command}.")]
+    WrongCommand { option: String, command: String, expected: &'static str },
+}
+
+/// Parses the arguments that follow the name of the binary.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, UsageError> {
+    let mut args = args.into_iter();
+    let command = match args.next() {
+        None => return Ok(Command::Repl),
+        Some(arg) if arg == "-h" || arg == "--help" => return Ok(Command::Help),
+        Some(arg) if matches!(arg.as_str(), "check" | "infer" | "eval" | "js") => arg,
+        Some(arg) => return Err(UsageError::UnknownCommand(arg)),
+    };
+
+    let mut options = Options::default();
+    let mut file = None;
+    while let Some(arg) = args.next() {
+        // --format=json works as well as --format json
+        let (arg, value) = match arg.split_once('=') {
+            Some((opt, value)) if opt.starts_with("--") => (opt.to_string(), Some(value.to_string())),
+            _ => (arg, None),
+        };
+        let mut value = || value.clone().or_else(|| args.next()).ok_or(UsageError::MissingValue(arg.clone()));
+
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--format" => options.format = match value()?.as_str() {
+                "human" => Format::Human,
+                "json" => Format::Json,
+                format => return Err(UsageError::UnknownFormat(format.to_string())),
+            },
+            "-I" | "--include" => options.include.push(value()?.into()),
+            "--vm" if command != "eval" => return Err(UsageError::WrongCommand { option: arg, command, expected: "eval" }),
+            "--vm" => options.vm = true,
+            "-" if file.is_none() => file = Some(None),
+            _ if arg.starts_with('-') && arg != "-" => return Err(UsageError::UnknownOption(arg)),
+            _ if file.is_none() => file = Some(Some(PathBuf::from(arg))),
+            _ => return Err(UsageError::ExtraArgument(arg)),
+        }
+    }
+    options.file = file.flatten();
+
+    Ok(match command.as_str() {
+        "check" => Command::Check(options),
+        "infer" => Command::Infer(options),
+        "js" => Command::Js(options),
+        _ => Command::Eval(options),
+    })
+}
+
+/// Runs a check, infer, eval or js command, printing to 'out' and 'err', and
+/// returns the exit code.
+pub fn run(command: &Command, input: &mut impl Read, out: &mut impl Write, err: &mut impl Write) -> io::Result<u8> {
+    let (options, kind) = match command {
+        Command::Check(options) => (options, "check"),
+        Command::Infer(options) => (options, "infer"),
+        Command::Eval(options) => (options, "eval"),
+        Command::Js(options) => (options, "js"),
+        Command::Repl | Command::Help => {
+            writeln!(out, "{USAGE}")?;
+            return Ok(0);
+        }
+    };
+
+    let (name, src) = match &options.file {
+        Some(path) => match std::fs::read_to_string(path) {
+            Ok(src) => (path.display().to_string(), src),
+            Err(e) => {
+                writeln!(err, "Couldn't read {}: {e}", path.display())?;
+                return Ok(EXIT_USAGE);
+            }
+        },
+        None => {
+            let mut src = String::new();
+            input.read_to_string(&mut src)?;
+            ("<stdin>".to_string(), src)
+        }
+    };
+
+    // imports are looked for next to the program first
+    let dir = options.file.as_deref()
+        .and_then(Path::parent)
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let mut loader = ModuleLoader::new(std::iter::once(dir).chain(options.include.iter().map(PathBuf::as_path)));
+    let (program, typed, mut diagnostics) = check(&mut loader, &src);
+    let sig = &typed.signature;
+
+    // the value of each export, printed and as JSON
+    let mut values: Vec<(Arc<str>, String, Json)> = vec![];
+    if kind == "eval" && diagnostics.is_empty() && options.vm {
+        match vm::compile_program(&mut loader, &program, &typed) {
+            Ok(code) => values = vm::run(&code).into_iter().map(|(n, v)| (n, v.to_string(), v.to_json())).collect(),
+            Err(e) => diagnostics.push(Diagnostic::new(&src, None, e.to_string(), e.to_json())),
+        }
+    } else if kind == "eval" && diagnostics.is_empty() {
+        match eval::eval_program(&mut loader, &program) {
+            Ok(items) => values = items.into_iter().map(|(n, v)| (n, v.to_string(), v.to_json())).collect(),
+            Err(e) => diagnostics.push(Diagnostic::new(&src, None, e.to_string(), e.to_json())),
+        }
+    }
+
+    let mut code = None;
+    if kind == "js" && diagnostics.is_empty() {
+        match js::emit_program(&mut loader, &program, &typed) {
+            Ok(js) => code = Some(js),
+            Err(e) => diagnostics.push(Diagnostic::new(&src, None, e.to_string(), e.to_json())),
+        }
+    }
+
+    match options.format {
+        Format::Human => {
+            if let Some(code) = &code {
+                write!(out, "{code}")?;
+            }
+            if kind == "infer" {
+                write!(out, "{sig}")?;
+            }
+            for (item, v, _) in &values {
+                writeln!(out, "{item} = {v}")?;
+            }
+            for d in &diagnostics {
+                match d.location {
+                    Some((line, column)) => writeln!(err, "{name}:{line}:{column}: {}", d.message)?,
+                    None => writeln!(err, "{name}: {}", d.message)?,
+                }
+            }
+        }
+        Format::Json => {
+            let mut fields = vec![("kind", kind.into()), ("file", name.as_str().into())];
+            let item = |n: &str, t: Json| vec![("kind", "item".into()), ("name", n.into()), ("type", t)];
+            if kind == "infer" {
+                let items = sig.items.iter()
+                    .map(|(n, t)| Json::object(item(n, t.to_json())))
+                    .collect();
+                fields.push(("items", Json::Array(items)));
+            }
+            if kind == "eval" {
+                let items = values.iter()
+                    .zip(&sig.items)
+                    .map(|((n, _, v), (_, t))| Json::object(item(n, t.to_json()).into_iter().chain([("value", v.clone())])))
+                    .collect();
+                fields.push(("items", Json::Array(items)));
+            }
+            if kind == "js" {
+                fields.push(("code", code.as_deref().map_or(Json::Null, Json::from)));
+            }
+            fields.push(("errors", Json::Array(diagnostics.iter().map(Diagnostic::to_json).collect())));
+            writeln!(out, "{}", Json::object(fields))?;
+        }
+    }
+
+    Ok(if diagnostics.is_empty() { 0 } else { EXIT_ERRORS })
+}
+
+/* An error along with its 1-based line and column in the source, if it has a
+ * place there */
+struct Diagnostic {
+    location: Option<(usize, usize)>,
+    message: String,
+    error: Json,
+}
+
+impl Diagnostic {
+    fn new(src: &str, pos: Option<usize>, message: String, error: Json) -> Diagnostic {
+        let location = pos.map(|pos| {
+            let before = &src[..pos.min(src.len())];
+            let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+            (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+        });
+        Diagnostic { location, message, error }
+    }
+
+    fn to_json(&self) -> Json {
+        let (line, column) = match self.location {
+            Some((line, column)) => ((line as u64).into(), (column as u64).into()),
+            None => (Json::Null, Json::Null),
+        };
+        Json::object([
+            ("kind", "diagnostic".into()),
+            ("line", line),
+            ("column", column),
+            ("message", self.message.as_str().into()),
+            ("error", self.error.clone()),
+        ])
+    }
+}
+
+/* Parses and infers 'src', returning the program, everything inferred about it
+ * and every error in it */
+fn check(loader: &mut ModuleLoader, src: &str) -> (Program, TypedProgram, Vec<Diagnostic>) {
+    let (program, spans, parse_errors) = ast::parse_program_spanned(src);
+    let typed = typck::infer_program_typed(loader, &program);
+
+    let mut diagnostics = parse_errors.iter()
+        .map(|err| {
+            let pos = match err {
+                ParseError::UnexpectedToken { pos, .. } | ParseError::InvalidToken { pos, .. } => *pos,
+            };
+            Diagnostic::new(src, Some(pos), err.to_string(), err.to_json())
+        })
+        .collect::<Vec<_>>();
+
+    // the declarations line up with their spans when listed in pre-order
+    let mut decls = vec![];
+    flatten(&program.decls, &mut decls);
+
+    for (err, node) in &typed.errors {
+        let span = match (node, err) {
+            (Some(node), _) => spans.nodes.get(*node).map(|n| n.span),
+            (None, TypeError::Import(e)) => decls.iter()
+                .zip(&spans.decls)
+                .find(|(decl, _)| matches!(decl, Declaration::Import { module } if module == e.module()))
+                .map(|(_, span)| span.span),
+            (None, _) => None,
+        };
+        diagnostics.push(Diagnostic::new(src, span.map(|s: Span| s.start), err.to_string(), err.to_json()));
+    }
+
+    (program, typed, diagnostics)
+}
+
+fn flatten<'a>(decls: &'a [Declaration], flat: &mut Vec<&'a Declaration>) {
+    for decl in decls {
+        flat.push(decl);
+        if let Declaration::Module { decls, .. } = decl {
+            flatten(decls, flat);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Result<Command, UsageError> {
+        parse_args(s.split_whitespace().map(str::to_string))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(args(""), Ok(Command::Repl));
+        assert_eq!(args("check -"), Ok(Command::Check(Options::default())));
+        assert_eq!(args("infer --format=json -I lib a.lam"), Ok(Command::Infer(Options {
+            file: Some("a.lam".into()),
+            format: Format::Json,
+            include: vec!["lib".into()],
+            vm: false,
+        })));
+        assert_eq!(args("check --format"), Err(UsageError::MissingValue("--format".into())));
+        assert_eq!(args("check --format xml"), Err(UsageError::UnknownFormat("xml".into())));
+        assert_eq!(args("check a.lam b.lam"), Err(UsageError::ExtraArgument("b.lam".into())));
+        assert_eq!(args("eval"), Ok(Command::Eval(Options::default())));
+        assert_eq!(args("js a.lam"), Ok(Command::Js(Options { file: Some("a.lam".into()), ..Options::default() })));
+        assert_eq!(args("run"), Err(UsageError::UnknownCommand("run".into())));
+        assert_eq!(args("eval --vm"), Ok(Command::Eval(Options { vm: true, ..Options::default() })));
+        assert_eq!(
+            args("check --vm a.lam"),
+            Err(UsageError::WrongCommand { option: "--vm".into(), command: "check".into(), expected: "eval" }),
+        );
+    }
+}
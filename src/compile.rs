@@ -0,0 +1,132 @@
+/*  What the backends share: the interpreter in eval.rs, the bytecode compiler
+ *  in vm.rs and the JavaScript emitter in js.rs all go through a program's
+ *  declarations the same way, binding what each of them exports and going
+ *  into the modules it imports. The two compilers only take well-typed
+ *  programs, since they rely on the inferred types. */
+
+use crate::module::{self, ModuleError, ModuleLoader};
+use crate::tast::TypedExpression;
+use crate::typck::TypeError;
+
+use ast::{Declaration, Expression};
+use thiserror::Error;
+
+use std::sync::Arc;
+
+/// An error found while compiling. Only well-typed programs can be compiled,
+/// since the backends rely on their types.
+#[derive(Debug, Error)]
+pub enum CompileError {
+    #[error("Can't compile a program with errors: {}",
+        .0.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(" ")
+    )]
+    Type(Vec<TypeError>),
+    #[error("Can't compile an expression that isn't well-typed.")]
+    IllTyped,
+    #[error("Variable {name} isn't bound.")]
+    Unbound { name: Arc<str> },
+    #[error("{0}")]
+    Import(#[from] ModuleError),
+}
+
+/* What some declarations export, by name */
+pub(crate) type Exports<T> = Vec<(Arc<str>, T)>;
+
+/* What a backend does with the declarations that Walker goes through */
+pub(crate) trait Backend {
+    /* What a let turns into, which is what its name gets bound to */
+    type Export: Clone;
+    type Error: From<ModuleError>;
+    /* The names in scope, see scope and set_scope */
+    type Scope: Default;
+
+    /* Turns the let of 'name', whose typed AST is 'typed' if there is one,
+     * into whatever its name is bound to. */
+    fn define(&mut self, name: &Arc<str>, e: &Expression, typed: Option<&TypedExpression>) -> Result<Self::Export, Self::Error>;
+
+    /* Binds 'name' for the declarations that follow */
+    fn bind(&mut self, name: Arc<str>, export: Self::Export);
+
+    fn scope(&self) -> Self::Scope;
+
+    fn set_scope(&mut self, scope: Self::Scope);
+
+    /* Called around the declarations of a module, or of an imported module
+     * with 'import' set, e.g. to mark where they start and end */
+    fn enter(&mut self, _name: &str, _import: bool) {}
+
+    fn leave(&mut self, _name: &str, _import: bool) {}
+}
+
+/* Goes through declarations for a backend. An imported module is gone through
+ * the first time it's imported, with a scope of its own, and its exports are
+ * reused after that. The loader only hands out modules that type check, which
+ * also rules out modules that import themselves. */
+pub(crate) struct Walker<'a, B: Backend> {
+    pub(crate) backend: B,
+    loader: &'a mut ModuleLoader,
+    modules: Vec<(Arc<str>, Exports<B::Export>)>,
+}
+
+impl<'a, B: Backend> Walker<'a, B> {
+    pub(crate) fn new(backend: B, loader: &'a mut ModuleLoader) -> Walker<'a, B> {
+        Walker { backend, loader, modules: vec![] }
+    }
+
+    /* Goes through 'decls', taking the typed ASTs of their lets from 'exprs' in
+     * order, and returns what they export. */
+    pub(crate) fn decls<'e>(
+        &mut self,
+        decls: &[Declaration],
+        exprs: &mut impl Iterator<Item = &'e TypedExpression>,
+    ) -> Result<Exports<B::Export>, B::Error> {
+        let mut exports = vec![];
+        for decl in decls {
+            match decl {
+                Declaration::Let { name, e } => {
+                    let export = self.backend.define(name, e, exprs.next())?;
+                    self.backend.bind(name.clone(), export.clone());
+                    exports.push((name.clone(), export));
+                }
+                Declaration::Import { module } => {
+                    for (name, export) in self.import(module)? {
+                        self.backend.bind(module::qualify(module, &name), export);
+                    }
+                }
+                Declaration::Module { name, decls } => {
+                    let outer = self.backend.scope();
+                    self.backend.enter(name, false);
+                    let inner = self.decls(decls, exprs)?;
+                    self.backend.leave(name, false);
+                    self.backend.set_scope(outer);
+
+                    for (item, export) in inner {
+                        let item = module::qualify(name, &item);
+                        self.backend.bind(item.clone(), export.clone());
+                        exports.push((item, export));
+                    }
+                }
+            }
+        }
+        Ok(exports)
+    }
+
+    fn import(&mut self, module: &Arc<str>) -> Result<Exports<B::Export>, B::Error> {
+        if let Some((_, exports)) = self.modules.iter().find(|(m, _)| m == module) {
+            return Ok(exports.clone());
+        }
+        let loaded = self.loader.load_typed(module)?;
+
+        // the module only sees its own declarations
+        let scope = self.backend.scope();
+        self.backend.set_scope(B::Scope::default());
+        self.backend.enter(module, true);
+        let exports = self.decls(&loaded.program.decls, &mut loaded.typed.exprs.iter());
+        self.backend.leave(module, true);
+        self.backend.set_scope(scope);
+
+        let exports = exports?;
+        self.modules.push((module.clone(), exports.clone()));
+        Ok(exports)
+    }
+}
@@ -1,41 +1,216 @@
-use crate::typ::{PolyType, MonoType, VarType};
+use crate::typ::{PolyType, Scheme, MonoType, VarType};
+use crate::typck::TypeError;
 use std::rc::Rc;
+use std::sync::Arc;
 use std::cell::RefCell;
 
-
+/// The typing context Γ, which maps the symbols in scope to their types. It
+/// also hands out fresh type variables and collects the errors found while
+/// inferring.
+///
+/// The types in a context are shared with `Rc` and `RefCell` so that unify can
+/// bind type variables in place, which means a context can't be sent to or
+/// shared with another thread. Contexts on different threads can share an
+/// environment as PolyTypes though, see insert_poly.
 pub struct TypeContext {
-    current_id: u8,
-    syms: Vec<(Rc<str>, Rc<PolyType>)>,
+    current_id: u32,
+    // the level given to fresh type variables, see enter_level
+    level: u32,
+    syms: Vec<(Arc<str>, Rc<Scheme>)>,
+    // symbols that were bound at some point but have since gone out of scope
+    out_of_scope: Vec<Arc<str>>,
+    // names defined elsewhere that aren't bound here, only used for suggestions
+    known: Vec<Arc<str>>,
+    // the post-order index of the next expression node to be inferred
+    node: usize,
+    // each error along with the node it was found at, if any
+    errors: Vec<(TypeError, Option<usize>)>,
+}
+
+impl Default for TypeContext {
+    fn default() -> TypeContext {
+        TypeContext::new()
+    }
 }
 
 impl TypeContext {
     pub fn new() -> TypeContext {
         TypeContext {
             current_id: 0,
+            level: 0,
             syms: vec![],
+            out_of_scope: vec![],
+            known: vec![],
+            node: 0,
+            errors: vec![],
         }
     }
 
-    pub fn insert_sym(&mut self, sym: Rc<str>, t: Rc<PolyType>) {
+    pub(crate) fn insert_sym(&mut self, sym: Arc<str>, t: Rc<Scheme>) {
         self.syms.push((sym, t));
     }
 
+    /// Binds 'sym' to an already inferred polytype, see PolyType::to_scheme.
+    pub fn insert_poly(&mut self, sym: Arc<str>, t: &PolyType) {
+        let t = t.to_scheme(self);
+        self.insert_sym(sym, Rc::new(t));
+    }
+
+    /// Makes 'sym' a candidate for similar_syms without binding it, for names
+    /// that are defined but were left out of this context on purpose.
+    pub fn insert_known(&mut self, sym: Arc<str>) {
+        self.known.push(sym);
+    }
+
     pub fn pop_sym(&mut self) {
-        self.syms.pop();
+        if let Some((sym, _)) = self.syms.pop() {
+            self.out_of_scope.push(sym);
+        }
+    }
+
+    /// The number of symbols in scope, to be passed to end_scope later on.
+    pub fn scope(&self) -> usize {
+        self.syms.len()
+    }
+
+    /// Pops every symbol inserted since 'scope' was taken.
+    pub fn end_scope(&mut self, scope: usize) {
+        while self.syms.len() > scope {
+            self.pop_sym();
+        }
+    }
+
+    /// Forgets every symbol inserted since 'scope' was taken, as if they had
+    /// never been bound. Unlike end_scope, they aren't remembered as being out
+    /// of scope.
+    pub fn discard_scope(&mut self, scope: usize) {
+        self.syms.truncate(scope);
     }
 
-    pub fn lookup_sym(&self, sym: &str) -> Option<Rc<PolyType>> {
+    pub(crate) fn lookup_sym(&self, sym: &str) -> Option<Rc<Scheme>> {
         self.syms.iter()
             .rev()
             .find(|(k, _)| &**k == sym)
             .map(|(_, t)| t.clone())
     }
 
-    pub fn fresh_variable(&mut self) -> MonoType {
-        let tvar = RefCell::new(VarType::Unbound { id: self.current_id });
+    /// The symbols in scope along with their types, in the order they were
+    /// bound. A symbol bound more than once only shows up at its latest binding.
+    pub fn bindings(&self) -> Vec<(Arc<str>, PolyType)> {
+        self.syms.iter()
+            .enumerate()
+            .filter(|(i, (k, _))| !self.syms[i + 1..].iter().any(|(s, _)| s == k))
+            .map(|(_, (k, t))| (k.clone(), t.zonk()))
+            .collect()
+    }
+
+    /* Finds the symbols in scope that are closest to 'sym' by edit distance,
+     * closest first. Symbols bound more recently win ties, and symbols in
+     * scope win over the ones from insert_known. */
+    pub fn similar_syms(&self, sym: &str) -> Vec<Arc<str>> {
+        let syms = self.syms.iter().rev().map(|(k, _)| k);
+        similar_names(sym, syms.chain(self.known.iter().rev()))
+    }
+
+    /* Whether 'sym' was bound somewhere but isn't in scope anymore */
+    pub fn was_in_scope(&self, sym: &str) -> bool {
+        self.out_of_scope.iter().any(|k| &**k == sym)
+    }
+
+    pub(crate) fn fresh_variable(&mut self) -> MonoType {
+        let tvar = RefCell::new(VarType::Unbound { id: self.current_id, level: self.level });
         let t = MonoType::Var { tvar };
         self.current_id += 1;
         t
     }
+
+    /// Starts inferring the right-hand side of a let. Type variables made from
+    /// here until the matching exit_level can be generalized afterwards, unless
+    /// unify ties them to a variable made before.
+    pub(crate) fn enter_level(&mut self) {
+        self.level += 1;
+    }
+
+    pub(crate) fn exit_level(&mut self) {
+        self.level -= 1;
+    }
+
+    /// How many lets deep inference currently is
+    pub(crate) fn level(&self) -> u32 {
+        self.level
+    }
+
+    /// Numbers the expression nodes in the order they're inferred, which is
+    /// post-order, the same order as ast::Spans::nodes. Returns the number of
+    /// the node that was just inferred.
+    pub(crate) fn next_node(&mut self) -> usize {
+        self.node += 1;
+        self.node - 1
+    }
+
+    /// Records an error found at the node being inferred and returns the error
+    /// type to be used in place of the type that couldn't be inferred.
+    pub(crate) fn report(&mut self, err: TypeError) -> Rc<MonoType> {
+        self.report_at(err, Some(self.node));
+        MonoType::Error.into()
+    }
+
+    /// Records an error found at 'node', or somewhere other than an expression.
+    pub(crate) fn report_at(&mut self, err: TypeError, node: Option<usize>) {
+        self.errors.push((err, node));
+    }
+
+    pub fn take_errors(&mut self) -> Vec<TypeError> {
+        self.take_located_errors()
+            .into_iter()
+            .map(|(err, _)| err)
+            .collect()
+    }
+
+    /// Takes the errors along with the nodes they were found at, see report.
+    pub fn take_located_errors(&mut self) -> Vec<(TypeError, Option<usize>)> {
+        std::mem::take(&mut self.errors)
+    }
 }
 
+
+/// Up to three of 'names' that are closest to 'sym' by edit distance, closest
+/// first. Names that are equally close keep their order in 'names'.
+pub(crate) fn similar_names<'a>(sym: &str, names: impl IntoIterator<Item = &'a Arc<str>>) -> Vec<Arc<str>> {
+    // allow an edit for every three characters, but at least one so that short
+    // names still get suggestions, even if every one letter name is one edit
+    // away from every other one
+    let max_dist = ((sym.chars().count() + 1) / 3).max(1);
+
+    let mut similar = Vec::<(usize, Arc<str>)>::new();
+    for k in names {
+        let dist = edit_distance(k, sym);
+        if dist > 0 && dist <= max_dist && !similar.iter().any(|(_, s)| s == k) {
+            similar.push((dist, k.clone()));
+        }
+    }
+
+    similar.sort_by_key(|(dist, _)| *dist);
+    similar.into_iter()
+        .take(3)
+        .map(|(_, k)| k)
+        .collect()
+}
+
+/* Levenshtein distance between two strings */
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    let mut curr = vec![0; b.len() + 1];
+
+    for (i, c_a) in a.chars().enumerate() {
+        curr[0] = i + 1;
+        for (j, c_b) in b.iter().enumerate() {
+            let subst = prev[j] + if c_a == *c_b { 0 } else { 1 };
+            curr[j + 1] = subst.min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+
+    prev[b.len()]
+}
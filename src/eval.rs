@@ -0,0 +1,300 @@
+/*  A call-by-value interpreter that closely follows the big-step semantics:
+ *
+ *      env ⊢ \x. e ⇓ closure(x, e, env)
+ *
+ *      env ⊢ f ⇓ closure(x, e, env')    env ⊢ a ⇓ v    env' + x = v ⊢ e ⇓ r
+ *      ---------------------------------------------------------------------
+ *      env ⊢ f a ⇓ r
+ *
+ *      env ⊢ e0 ⇓ v    env + x = v ⊢ e1 ⇓ r
+ *      ------------------------------------
+ *      env ⊢ let x = e0 in e1 ⇓ r
+ *
+ *  Closures capture the environment they were made in, so nothing is ever
+ *  substituted. Expressions that aren't well-typed can get stuck, e.g. when
+ *  applying true to something, which is reported as an EvalError. Programs
+ *  that type check never do, see the tests.
+ *
+ *  Expressions are first turned into a Term, which shares its sub-terms with
+ *  Rc so that closures can point into it without copying. */
+
+use crate::compile::{Backend, Walker};
+use crate::module::{ModuleError, ModuleLoader};
+use crate::tast::TypedExpression;
+
+use ast::{Expression, Program};
+use thiserror::Error;
+
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// The result of evaluating an expression.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Bool(bool),
+    Closure(Rc<Closure>),
+}
+
+/// A function along with the environment it was made in.
+#[derive(Debug)]
+pub struct Closure {
+    name: Arc<str>,
+    body: Rc<Term>,
+    env: Env,
+}
+
+/// Functions have no way of being printed back as source, so they're all
+/// printed the same.
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Closure(_) => write!(f, "<function>"),
+        }
+    }
+}
+
+/// The values of a program's declarations along with their names, in the
+/// order they were declared.
+pub type Values = Vec<(Arc<str>, Value)>;
+
+/// Where an expression got stuck.
+#[derive(Debug, Error)]
+pub enum EvalError {
+    #[error("Variable {name} isn't bound to a value.")]
+    Unbound { name: Arc<str> },
+    #[error("{value} can't be applied to an argument, since it isn't a function.")]
+    NotAFunction { value: Value },
+    #[error("Can't evaluate an expression that failed to parse.")]
+    Invalid,
+    #[error("{0}")]
+    Import(#[from] ModuleError),
+}
+
+/// The values of the variables in scope, which closures share with the
+/// environment they were made in.
+#[derive(Debug, Clone, Default)]
+pub struct Env(Option<Rc<Binding>>);
+
+#[derive(Debug)]
+struct Binding {
+    name: Arc<str>,
+    value: Value,
+    next: Env,
+}
+
+impl Env {
+    pub fn new() -> Env {
+        Env(None)
+    }
+
+    /// This environment with 'name' bound to 'value' on top.
+    pub fn bind(&self, name: Arc<str>, value: Value) -> Env {
+        Env(Some(Rc::new(Binding { name, value, next: self.clone() })))
+    }
+
+    /// The value of the latest binding of 'name'
+    pub fn lookup(&self, name: &str) -> Option<&Value> {
+        let mut env = self;
+        while let Some(binding) = &env.0 {
+            if &*binding.name == name {
+                return Some(&binding.value);
+            }
+            env = &binding.next;
+        }
+        None
+    }
+}
+
+#[derive(Debug)]
+enum Term {
+    Var(Arc<str>),
+    App(Rc<Term>, Rc<Term>),
+    Abs(Arc<str>, Rc<Term>),
+    Let(Arc<str>, Rc<Term>, Rc<Term>),
+    Bool(bool),
+    Error,
+}
+
+impl From<&Expression> for Term {
+    fn from(e: &Expression) -> Term {
+        match e {
+            Expression::Var { name } => Term::Var(name.clone()),
+            Expression::App { f, e } => Term::App(Rc::new(Term::from(&**f)), Rc::new(Term::from(&**e))),
+            Expression::Abs { name, e } => Term::Abs(name.clone(), Rc::new(Term::from(&**e))),
+            Expression::Let { name, e0, e1 } => {
+                Term::Let(name.clone(), Rc::new(Term::from(&**e0)), Rc::new(Term::from(&**e1)))
+            }
+            Expression::True => Term::Bool(true),
+            Expression::False => Term::Bool(false),
+            Expression::Error => Term::Error,
+        }
+    }
+}
+
+fn eval_term(env: &Env, t: &Rc<Term>) -> Result<Value, EvalError> {
+    match &**t {
+        Term::Var(name) => env.lookup(name)
+            .cloned()
+            .ok_or_else(|| EvalError::Unbound { name: name.clone() }),
+        Term::App(f, a) => {
+            let f = eval_term(env, f)?;
+            let a = eval_term(env, a)?;
+            match f {
+                Value::Closure(c) => eval_term(&c.env.bind(c.name.clone(), a), &c.body),
+                value => Err(EvalError::NotAFunction { value }),
+            }
+        }
+        Term::Abs(name, body) => Ok(Value::Closure(Rc::new(Closure {
+            name: name.clone(),
+            body: body.clone(),
+            env: env.clone(),
+        }))),
+        Term::Let(name, e0, e1) => {
+            let v = eval_term(env, e0)?;
+            eval_term(&env.bind(name.clone(), v), e1)
+        }
+        Term::Bool(b) => Ok(Value::Bool(*b)),
+        Term::Error => Err(EvalError::Invalid),
+    }
+}
+
+/// Evaluates a closed expression.
+pub fn eval(e: &Expression) -> Result<Value, EvalError> {
+    eval_in(&Env::new(), e)
+}
+
+/// Evaluates 'e' with the variables bound in 'env'.
+pub fn eval_in(env: &Env, e: &Expression) -> Result<Value, EvalError> {
+    eval_term(env, &Rc::new(Term::from(e)))
+}
+
+/// Evaluates the declarations of a program in order and returns the values of
+/// what it exports, named like in its Signature. Imported modules are found
+/// with 'loader' and evaluated along the way.
+pub fn eval_program(loader: &mut ModuleLoader, program: &Program) -> Result<Values, EvalError> {
+    eval_program_in(loader, &Env::new(), program).map(|(_, items)| items)
+}
+
+/// Like eval_program, but starts out with the variables bound in 'env', and
+/// also returns 'env' with everything the program binds added to it.
+pub fn eval_program_in(loader: &mut ModuleLoader, env: &Env, program: &Program) -> Result<(Env, Values), EvalError> {
+    let mut walker = Walker::new(Evaluator { env: env.clone() }, loader);
+    let items = walker.decls(&program.decls, &mut std::iter::empty())?;
+    Ok((walker.backend.env, items))
+}
+
+/* Evaluates each let as it's declared, in the environment of the declarations
+ * before it */
+struct Evaluator {
+    env: Env,
+}
+
+impl Backend for Evaluator {
+    type Export = Value;
+    type Error = EvalError;
+    type Scope = Env;
+
+    fn define(&mut self, _: &Arc<str>, e: &Expression, _: Option<&TypedExpression>) -> Result<Value, EvalError> {
+        eval_in(&self.env, e)
+    }
+
+    fn bind(&mut self, name: Arc<str>, v: Value) {
+        self.env = self.env.bind(name, v);
+    }
+
+    fn scope(&self) -> Env {
+        self.env.clone()
+    }
+
+    fn set_scope(&mut self, env: Env) {
+        self.env = env;
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::typck::infer;
+
+    fn eval_str(src: &str) -> String {
+        match eval(&ast::parse(src).unwrap()) {
+            Ok(v) => v.to_string(),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(eval_str(r"(\x. \y. x) true false"), "true");
+        assert_eq!(eval_str(r"\x. x"), "<function>");
+        assert_eq!(eval_str(r"let x = false in let f = \y. x in let x = true in f x"), "false");
+
+        // Church numerals and booleans, read back as booleans
+        let not = r"\b. \t. \f. b f t";
+        let three = r"\f. \x. f (f (f x))";
+        assert_eq!(eval_str(&format!(r"let not = {not} in ({three}) not (\t. \f. t) true false")), "false");
+
+        assert_eq!(eval_str("true false"), "true can't be applied to an argument, since it isn't a function.");
+        assert_eq!(eval_str("x"), "Variable x isn't bound to a value.");
+    }
+
+    /* A xorshift generator, which is all the randomness the tests need */
+    pub(crate) struct Rng(pub(crate) u64);
+
+    impl Rng {
+        fn below(&mut self, n: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % n
+        }
+    }
+
+    /* A random closed expression, which the vm tests also use */
+    pub(crate) fn gen_expr(rng: &mut Rng, depth: u32, scope: &mut Vec<Arc<str>>) -> Expression {
+        let leaf = depth == 0 || rng.below(4) == 0;
+        match rng.below(if leaf { 3 } else { 6 }) {
+            0 => Expression::True,
+            1 if scope.is_empty() => Expression::False,
+            1 => Expression::Var { name: scope[rng.below(scope.len() as u64) as usize].clone() },
+            2 => Expression::False,
+            3 => Expression::App {
+                f: gen_expr(rng, depth - 1, scope).into(),
+                e: gen_expr(rng, depth - 1, scope).into(),
+            },
+            4 => {
+                let name: Arc<str> = format!("x{}", scope.len()).into();
+                scope.push(name.clone());
+                let e = gen_expr(rng, depth - 1, scope);
+                scope.pop();
+                Expression::Abs { name, e: e.into() }
+            }
+            _ => {
+                let name: Arc<str> = format!("x{}", scope.len()).into();
+                let e0 = gen_expr(rng, depth - 1, scope);
+                scope.push(name.clone());
+                let e1 = gen_expr(rng, depth - 1, scope);
+                scope.pop();
+                Expression::Let { name, e0: e0.into(), e1: e1.into() }
+            }
+        }
+    }
+
+    #[test]
+    fn test_well_typed_programs_dont_get_stuck() {
+        let mut rng = Rng(0x9e3779b97f4a7c15);
+        let mut well_typed = 0;
+        for _ in 0..20000 {
+            let e = gen_expr(&mut rng, 6, &mut vec![]);
+            if infer(e.clone()).is_ok() {
+                well_typed += 1;
+                if let Err(err) = eval(&e) {
+                    panic!("{e} is well-typed but got stuck: {err}");
+                }
+            }
+        }
+        // make sure the generator isn't only coming up with trivial programs
+        assert!(well_typed > 1000, "only {well_typed} of the programs were well-typed");
+    }
+}
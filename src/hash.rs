@@ -0,0 +1,58 @@
+/*  Content hashes that stay the same across runs, platforms and compiler
+ *  versions, unlike std's DefaultHasher, so they can be written to disk.
+ *
+ *  This is 64 bit FNV-1a, see http://www.isthe.com/chongo/tech/comp/fnv/ */
+
+use std::hash::Hasher;
+
+const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const PRIME: u64 = 0x100000001b3;
+
+/// A stable hasher for cache keys.
+#[derive(Debug, Clone, Copy)]
+pub struct ContentHasher(u64);
+
+impl Default for ContentHasher {
+    fn default() -> ContentHasher {
+        ContentHasher(OFFSET_BASIS)
+    }
+}
+
+impl ContentHasher {
+    /// Hashes 's' followed by a terminator, so that writing "ab" then "c"
+    /// doesn't hash the same as writing "a" then "bc".
+    pub fn write_str(&mut self, s: &str) {
+        self.write(s.as_bytes());
+        self.write_u8(0xff);
+    }
+}
+
+impl Hasher for ContentHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(PRIME);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fnv1a() {
+        let hash = |s: &str| {
+            let mut h = ContentHasher::default();
+            h.write(s.as_bytes());
+            h.finish()
+        };
+        assert_eq!(hash(""), 0xcbf29ce484222325);
+        assert_eq!(hash("a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(hash("foobar"), 0x85944171f73967e8);
+    }
+}
@@ -0,0 +1,213 @@
+/*  Incremental inference for editors, where a program gets inferred again
+ *  after every change even though most of its definitions stay the same.
+ *
+ *  Every definition is inferred in a context that only holds its dependencies,
+ *  like in sched.rs, so its result only depends on its name, its source, the
+ *  types of its dependencies and the names that would be suggested for the
+ *  names it uses that aren't defined. Those make up a key, and results are
+ *  reused for as long as their key doesn't change. Entries are looked up by
+ *  the key's hash, but the key itself is kept along with the result and
+ *  compared as well, so two keys that happen to hash the same aren't confused.
+ *
+ *  Keys use the types of the dependencies rather than their source, so a
+ *  change that doesn't affect a definition's type doesn't spread any further
+ *  than the definition itself. */
+
+use crate::ctx;
+use crate::hash::ContentHasher;
+use crate::sched::{self, Inferred};
+use crate::typ::PolyType;
+
+use ast::Expression;
+
+use std::collections::HashMap;
+use std::hash::Hasher;
+use std::sync::Arc;
+
+/// Caches the results of inferring a program's definitions between runs.
+#[derive(Debug, Default)]
+pub struct QueryCache {
+    entries: HashMap<u64, (Key, Arc<Inferred>)>,
+    // definitions that had to be inferred during the last run
+    reinferred: Vec<usize>,
+}
+
+impl QueryCache {
+    pub fn new() -> QueryCache {
+        QueryCache::default()
+    }
+
+    /// Infers 'defs' like infer_parallel, reusing the results from the previous
+    /// run for definitions that haven't changed and whose dependencies' types
+    /// haven't changed either. Types that a definition uses from 'env' count
+    /// as its dependencies too.
+    ///
+    /// Only the results of this run are kept for the next one, so the cache
+    /// doesn't grow as the program gets edited.
+    pub fn infer(&mut self, env: &[(Arc<str>, PolyType)], defs: &[(Arc<str>, Expression)]) -> Vec<Arc<Inferred>> {
+        let deps = sched::dependencies(defs);
+        let mut entries = HashMap::new();
+        let mut results = Vec::<Arc<Inferred>>::with_capacity(defs.len());
+        self.reinferred.clear();
+
+        for (i, (name, e)) in defs.iter().enumerate() {
+            let mut scope = deps[i].iter()
+                .map(|j| (results[*j].name.clone(), results[*j].typ.clone()))
+                .collect::<Vec<_>>();
+            // the names that no earlier definition binds come from 'env', if any
+            for v in e.free_vars() {
+                if !scope.iter().any(|(n, _)| *n == v) && let Some(t) = env.iter().rev().find(|(n, _)| *n == v) {
+                    scope.push(t.clone());
+                }
+            }
+            let known = env.iter().map(|(n, _)| n).chain(defs[..i].iter().map(|(n, _)| n));
+            let similar = e.free_vars()
+                .iter()
+                .filter(|v| !scope.iter().any(|(n, _)| n == *v))
+                .map(|v| ctx::similar_names(v, known.clone()))
+                .collect::<Vec<_>>();
+            let key = Key::new(name, e, &scope, similar);
+            let hash = key.hash();
+
+            let inferred = match self.entries.get(&hash) {
+                Some((k, inferred)) if *k == key => inferred.clone(),
+                _ => {
+                    self.reinferred.push(i);
+                    Arc::new(sched::infer_definition(&scope, known, name, e))
+                }
+            };
+            entries.insert(hash, (key, inferred.clone()));
+            results.push(inferred);
+        }
+
+        self.entries = entries;
+        results
+    }
+
+    /// The indices of the definitions that the last call to infer couldn't
+    /// reuse a result for.
+    pub fn reinferred(&self) -> &[usize] {
+        &self.reinferred
+    }
+}
+
+/* Everything the result of inferring a definition depends on */
+#[derive(Debug, PartialEq, Eq)]
+struct Key {
+    name: Arc<str>,
+    source: String,
+    // the dependencies along with their types
+    scope: Vec<(Arc<str>, String)>,
+    similar: Vec<Vec<Arc<str>>>,
+}
+
+impl Key {
+    fn new(name: &Arc<str>, e: &Expression, scope: &[(Arc<str>, PolyType)], similar: Vec<Vec<Arc<str>>>) -> Key {
+        Key {
+            name: name.clone(),
+            source: e.to_string(),
+            scope: scope.iter().map(|(dep, t)| (dep.clone(), t.to_string())).collect(),
+            similar,
+        }
+    }
+
+    fn hash(&self) -> u64 {
+        let mut h = ContentHasher::default();
+        h.write_str(&self.name);
+        h.write_str(&self.source);
+        for (dep, t) in &self.scope {
+            h.write_str(dep);
+            h.write_str(t);
+        }
+        for names in &self.similar {
+            h.write_usize(names.len());
+            for n in names {
+                h.write_str(n);
+            }
+        }
+        h.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defs(src: &str) -> Vec<(Arc<str>, Expression)> {
+        ast::parse_program(src).unwrap().definitions()
+    }
+
+    #[test]
+    fn test_reuse() {
+        let mut cache = QueryCache::new();
+        let src = "let id = \\x. x\nlet k = \\x. \\y. x\nlet a = id true\nlet b = k id\nlet c = a";
+        let first = cache.infer(&[], &defs(src));
+        assert_eq!(cache.reinferred(), [0, 1, 2, 3, 4]);
+
+        cache.infer(&[], &defs(src));
+        assert_eq!(cache.reinferred(), []);
+
+        // id's type stays the same, so only id itself is inferred again
+        let second = cache.infer(&[], &defs(&src.replace("\\x. x\n", "\\y. (\\z. z) y\n")));
+        assert_eq!(cache.reinferred(), [0]);
+        assert!(Arc::ptr_eq(&first[3], &second[3]));
+
+        // a's type changes, which spreads to c but not to b
+        let third = cache.infer(&[], &defs(&src.replace("id true", "id")));
+        assert_eq!(cache.reinferred(), [0, 2, 4]);
+        assert_eq!(third[4].typ.to_string(), "forall a. a -> a");
+
+        let fresh = sched::infer_parallel(&[], &defs(&src.replace("id true", "id")), 1);
+        for (cached, fresh) in third.iter().zip(&fresh) {
+            assert_eq!(cached.typ, fresh.typ);
+        }
+    }
+
+    #[test]
+    fn test_similar_names() {
+        let mut cache = QueryCache::new();
+        let src = "let a = idd true\nlet b = true";
+        let first = cache.infer(&[], &defs(src));
+        assert_eq!(first[0].errors[0].0.to_string(), "Variable idd not found.");
+
+        // defining a name that would be suggested changes the error, so a is
+        // inferred again, while b doesn't use any name that isn't defined
+        let src = format!("let id = \\x. x\n{src}");
+        let second = cache.infer(&[], &defs(&src));
+        assert_eq!(cache.reinferred(), [0, 1]);
+        assert_eq!(second[1].errors[0].0.to_string(), "Variable idd not found. Did you mean id?");
+    }
+
+    #[test]
+    fn test_env() {
+        let env = |t: &str| [("M.f".into(), crate::parse_type(t).unwrap())];
+        let mut cache = QueryCache::new();
+        let src = "let a = M.f true\nlet b = \\x. x\nlet c = a";
+        cache.infer(&env("forall a. a -> a"), &defs(src));
+        assert_eq!(cache.reinferred(), [0, 1, 2]);
+
+        // only the definitions that use M.f are affected when its type changes
+        let results = cache.infer(&env("forall a. a -> a -> a"), &defs(src));
+        assert_eq!(cache.reinferred(), [0, 2]);
+        assert_eq!(results[2].typ.to_string(), "Bool -> Bool");
+    }
+
+    #[test]
+    fn test_hash_collision() {
+        let mut cache = QueryCache::new();
+        let src = "let a = true\nlet b = \\x. x";
+        cache.infer(&[], &defs(src));
+
+        // make a's entry look like it belongs to some other definition whose
+        // key happens to hash the same
+        for (key, _) in cache.entries.values_mut() {
+            if &*key.name == "a" {
+                key.source = "false".to_string();
+            }
+        }
+
+        let results = cache.infer(&[], &defs(src));
+        assert_eq!(cache.reinferred(), [0]);
+        assert_eq!(results[0].typ.to_string(), "Bool");
+    }
+}
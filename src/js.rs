@@ -0,0 +1,323 @@
+/*  Emits JavaScript from the typed AST, so that programs can be run anywhere
+ *  node or a browser is. The output is meant to be read:
+ *
+ *      let id = \x. x                  // id : forall a. a -> a
+ *      let b = let y = true in id y    const id = (x) => x;
+ *                                      // b : Bool
+ *                                      const b = (() => {
+ *                                        const y = true;
+ *                                        return id(y);
+ *                                      })();
+ *
+ *  Lambdas become arrow functions and lets become consts. Types only show up
+ *  in comments, since JavaScript doesn't need to know that a let is
+ *  polymorphic. Every binder gets a JavaScript name of its own, so shadowing
+ *  and names that mean something to JavaScript can't get in the way, and
+ *  modules, imported or not, are emitted in place with their declarations
+ *  reachable under their qualified names.
+ *
+ *  The script exports the program's signature through module.exports, and
+ *  prints each export when it's run by itself, like the eval command does. */
+
+use crate::compile::{Backend, CompileError, Walker};
+use crate::module::ModuleLoader;
+use crate::tast::{TypedExpression, TypedKind};
+use crate::typ::PolyType;
+use crate::typck::TypedProgram;
+
+use ast::{Expression, Program};
+
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::sync::Arc;
+
+// words that JavaScript won't take as a name, or that the script itself uses
+const RESERVED: &[&str] = &[
+    "arguments", "await", "break", "case", "catch", "class", "const", "continue", "debugger",
+    "default", "delete", "do", "else", "enum", "eval", "export", "extends", "false", "finally",
+    "for", "function", "if", "implements", "import", "in", "instanceof", "interface", "let",
+    "new", "null", "package", "private", "protected", "public", "return", "static", "super",
+    "switch", "this", "throw", "true", "try", "typeof", "var", "void", "while", "with", "yield",
+    "undefined", "NaN", "Infinity", "console", "module", "require", "exports", "Object",
+];
+
+const MAIN: &str = r#"if (require.main === module) {
+  for (const [name, value] of Object.entries(module.exports)) {
+    console.log(`${name} = ${typeof value === "function" ? "<function>" : value}`);
+  }
+}
+"#;
+
+/// Emits a closed typed expression as a JavaScript expression.
+pub fn emit_expr(e: &TypedExpression) -> Result<String, CompileError> {
+    Emitter::new().expr(e, 0)
+}
+
+/// Emits a program that was inferred with infer_program_typed as a script,
+/// along with the modules it imports, which are found with 'loader'. The
+/// program has to be free of type errors.
+pub fn emit_program(loader: &mut ModuleLoader, program: &Program, typed: &TypedProgram) -> Result<String, CompileError> {
+    if !typed.errors.is_empty() {
+        return Err(CompileError::Type(typed.errors.iter().map(|(err, _)| err.clone()).collect()));
+    }
+
+    let mut walker = Walker::new(Emitter::new(), loader);
+    let exports = walker.decls(&program.decls, &mut typed.exprs.iter())?;
+
+    let mut out = String::from("\"use strict\";\n\n");
+    out += &walker.backend.out;
+    out += "\nmodule.exports = {\n";
+    for (name, js) in exports {
+        match *name == *js {
+            true => writeln!(out, "  {js},").unwrap(),
+            false => writeln!(out, "  {}: {js},", quote(&name)).unwrap(),
+        }
+    }
+    out += "};\n\n";
+    out += MAIN;
+    Ok(out)
+}
+
+// a JSON string is also a JavaScript one
+fn quote(s: &str) -> String {
+    crate::ser::Json::from(s).to_string()
+}
+
+struct Emitter {
+    // every name given out so far
+    used: HashSet<String>,
+    // the binders in scope along with their JavaScript names, latest last
+    scope: Vec<(Arc<str>, String)>,
+    // the declarations emitted so far
+    out: String,
+}
+
+impl Emitter {
+    fn new() -> Emitter {
+        Emitter {
+            used: RESERVED.iter().map(|s| s.to_string()).collect(),
+            scope: vec![],
+            out: String::new(),
+        }
+    }
+
+    /* A JavaScript name for 'name' that hasn't been given out yet. Names can
+     * have a ' in them or letters outside of ASCII, which are spelled out. */
+    fn fresh(&mut self, name: &str) -> String {
+        let mut base = String::new();
+        for c in name.chars() {
+            match c {
+                c if c.is_ascii_alphanumeric() || c == '_' => base.push(c),
+                '\'' => base.push('$'),
+                c => write!(base, "$u{:x}", c as u32).unwrap(),
+            }
+        }
+
+        let mut js = base.clone();
+        let mut n = 1;
+        while self.used.contains(&js) {
+            js = format!("{base}${n}");
+            n += 1;
+        }
+        self.used.insert(js.clone());
+        js
+    }
+
+    fn lookup(&self, name: &Arc<str>) -> Result<String, CompileError> {
+        self.scope.iter()
+            .rev()
+            .find(|(n, _)| n == name)
+            .map(|(_, js)| js.clone())
+            .ok_or_else(|| CompileError::Unbound { name: name.clone() })
+    }
+
+    /* Emits 'e' as an expression, where 'indent' is the indentation of the
+     * line it starts on. */
+    fn expr(&mut self, e: &TypedExpression, indent: usize) -> Result<String, CompileError> {
+        if e.typ.has_error() {
+            return Err(CompileError::IllTyped);
+        }
+
+        Ok(match &e.kind {
+            TypedKind::Var { name } => self.lookup(name)?,
+            TypedKind::App { f, e: arg } => {
+                let f_js = self.expr(f, indent)?;
+                let arg = self.expr(arg, indent)?;
+                match f.kind {
+                    TypedKind::Abs { .. } => format!("({f_js})({arg})"),
+                    _ => format!("{f_js}({arg})"),
+                }
+            }
+            TypedKind::Abs { name, e: body } => {
+                let param = self.fresh(name);
+                self.scope.push((name.clone(), param.clone()));
+                let body = match body.kind {
+                    TypedKind::Let { .. } => self.block(body, indent)?,
+                    _ => self.expr(body, indent)?,
+                };
+                self.scope.pop();
+                format!("({param}) => {body}")
+            }
+            TypedKind::Let { .. } => format!("(() => {})()", self.block(e, indent)?),
+            TypedKind::True => "true".to_string(),
+            TypedKind::False => "false".to_string(),
+            TypedKind::Error => return Err(CompileError::IllTyped),
+        })
+    }
+
+    /* Emits 'e' as the body of a function, with a const for each let it
+     * starts with. */
+    fn block(&mut self, mut e: &TypedExpression, indent: usize) -> Result<String, CompileError> {
+        let pad = "  ".repeat(indent + 1);
+        let mut block = String::from("{\n");
+        let scope = self.scope.len();
+        while let TypedKind::Let { name, e0, e1, .. } = &e.kind {
+            let value = self.expr(e0, indent + 1)?;
+            let js = self.fresh(name);
+            writeln!(block, "{pad}const {js} = {value};").unwrap();
+            self.scope.push((name.clone(), js));
+            e = e1;
+        }
+        let result = self.expr(e, indent + 1)?;
+        self.scope.truncate(scope);
+        writeln!(block, "{pad}return {result};").unwrap();
+        block += &"  ".repeat(indent);
+        block += "}";
+        Ok(block)
+    }
+}
+
+/* Each let becomes a const, with its type in a comment above it */
+impl Backend for Emitter {
+    type Export = String;
+    type Error = CompileError;
+    type Scope = Vec<(Arc<str>, String)>;
+
+    fn define(&mut self, name: &Arc<str>, _: &Expression, typed: Option<&TypedExpression>) -> Result<String, CompileError> {
+        let e = typed.expect("every let has a typed AST");
+        let value = self.expr(e, 0)?;
+        let js = self.fresh(name);
+        let poly = PolyType::generalize_all(e.typ.clone());
+        writeln!(self.out, "// {name} : {poly}\nconst {js} = {value};").unwrap();
+        Ok(js)
+    }
+
+    fn bind(&mut self, name: Arc<str>, js: String) {
+        self.scope.push((name, js));
+    }
+
+    fn scope(&self) -> Self::Scope {
+        self.scope.clone()
+    }
+
+    fn set_scope(&mut self, scope: Self::Scope) {
+        self.scope = scope;
+    }
+
+    fn enter(&mut self, name: &str, import: bool) {
+        match import {
+            true  => writeln!(self.out, "// import {name}").unwrap(),
+            false => writeln!(self.out, "// module {name}").unwrap(),
+        }
+    }
+
+    fn leave(&mut self, name: &str, import: bool) {
+        match import {
+            true  => writeln!(self.out, "// end of import {name}").unwrap(),
+            false => writeln!(self.out, "// end of module {name}").unwrap(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval;
+    use crate::eval::tests::{Rng, gen_expr};
+    use crate::typck::infer_typed;
+
+    use std::process::Command;
+
+    /* Runs a script with node, from a file so that it's the main module, and
+     * returns what it printed. The tests that use it are ignored unless asked
+     * for with cargo test -- --ignored, since they need node to be installed. */
+    fn node(name: &str, script: &str) -> String {
+        let path = std::env::temp_dir().join(format!("algorithm-j-{}-{name}.js", std::process::id()));
+        std::fs::write(&path, script).unwrap();
+        let out = Command::new("node").arg(&path).output();
+        std::fs::remove_file(&path).unwrap();
+        let out = out.unwrap_or_else(|err| panic!("couldn't run node, which the JavaScript tests need: {err}"));
+        assert!(out.status.success(), "node failed on\n{script}\n{}", String::from_utf8_lossy(&out.stderr));
+        String::from_utf8(out.stdout).unwrap()
+    }
+
+    const PROGRAM: &str = "let id = \\x. x\nlet class = let y = true in id y\nmodule M = struct\n  let id = \\x'. id x'\nend\nlet c = M.id class";
+
+    fn emit(src: &str) -> String {
+        let program = ast::parse_program(src).unwrap();
+        let mut loader = ModuleLoader::default();
+        let typed = crate::infer_program_typed(&mut loader, &program);
+        emit_program(&mut loader, &program, &typed).unwrap()
+    }
+
+    #[test]
+    fn test_emit_program() {
+        let js = emit(PROGRAM);
+        assert_eq!(js.strip_suffix(MAIN).unwrap(), r#""use strict";
+
+// id : forall a. a -> a
+const id = (x) => x;
+// class : Bool
+const class$1 = (() => {
+  const y = true;
+  return id(y);
+})();
+// module M
+// id : forall a. a -> a
+const id$1 = (x$) => id(x$);
+// end of module M
+// c : Bool
+const c = id$1(class$1);
+
+module.exports = {
+  id,
+  "class": class$1,
+  "M.id": id$1,
+  c,
+};
+
+"#);
+    }
+
+    #[test]
+    #[ignore = "needs node"]
+    fn test_run_program() {
+        // node runs the script with require.main set to it
+        let out = node("program", &emit(PROGRAM));
+        assert_eq!(out, "id = <function>\nclass = true\nM.id = <function>\nc = true\n");
+    }
+
+    #[test]
+    #[ignore = "needs node"]
+    fn test_same_as_eval() {
+        let mut rng = Rng(0xd1b54a32d192ed03);
+        let mut script = String::from("\"use strict\";\nconst show = (v) => typeof v === \"function\" ? \"<function>\" : String(v);\n");
+        let mut expected = String::new();
+        for _ in 0..3000 {
+            let e = gen_expr(&mut rng, 6, &mut vec![]);
+            let (typed, errors) = infer_typed(e.clone());
+            if errors.is_empty() {
+                writeln!(script, "console.log(show({}));", emit_expr(&typed).unwrap()).unwrap();
+                writeln!(expected, "{}", eval::eval(&e).unwrap()).unwrap();
+            }
+        }
+
+        assert!(expected.lines().count() > 100, "only {} of the programs were well-typed", expected.lines().count());
+
+        let out = node("exprs", &script);
+        for (i, (actual, expected)) in out.lines().zip(expected.lines()).enumerate() {
+            assert_eq!(actual, expected, "line {} of the script ran differently", i + 3);
+        }
+        assert_eq!(out.lines().count(), expected.lines().count());
+    }
+}
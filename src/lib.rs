@@ -0,0 +1,131 @@
+//! An implementation of Algorithm J, which infers Hindley-Milner types for a small lambda calculus
+//! with let-polymorphism and booleans.
+//!
+//! ```
+//! let e = algorithm_j::ast::parse(r"let id = \x. x in id").unwrap();
+//! let t = algorithm_j::infer(e).unwrap();
+//! assert_eq!(t.to_string(), "forall a. a -> a");
+//! ```
+//!
+//! - [`infer`] returns the type of an expression, or the first [`TypeError`].
+//! - [`infer_tolerant`] keeps going after errors and returns all of them.
+//! - [`infer_typed`] returns a [`TypedExpression`] with the type of every sub-expression.
+//! - [`infer_program`] infers every declaration of an [`ast::Program`] and returns their
+//!   [`Signature`]. [`infer_program_with`] also loads the modules it imports through a
+//!   [`ModuleLoader`].
+//! - [`infer_parallel`] infers a list of top-level definitions on several threads, each one as
+//!   soon as the definitions it uses are done.
+//!
+//! Inferred types are [`PolyType`]s, which can be parsed back with [`parse_type`] and compared
+//! with [`PolyType::alpha_eq`].
+//!
+//! Results can be written out as JSON or S-expressions and read back with the [`ser`] module.
+//!
+//! Well-typed programs can be run with the interpreter in the [`eval`] module, or compiled to
+//! bytecode and run on the machine in the [`vm`] module. The [`js`] module compiles them to
+//! JavaScript instead. Both report a [`compile::CompileError`] for programs they can't compile.
+//!
+//! Only the results of inference are thread-safe. Types are shared with `Rc` and `RefCell` while
+//! inference runs, so a [`TypeContext`] is neither `Send` nor `Sync` and stays on the thread it was
+//! made on. Everything inference hands back (expressions, [`PolyType`]s, [`TypedExpression`]s and
+//! [`TypeError`]s) is `Send + Sync` though, so independent programs can be checked on separate
+//! threads, each with a context of its own. An environment of [`PolyType`]s can be shared between
+//! those threads and put into each context with [`TypeContext::insert_poly`].
+
+pub(crate) mod typ;
+pub(crate) mod ctx;
+pub(crate) mod typck;
+pub(crate) mod tast;
+pub(crate) mod sched;
+pub mod incr;
+pub mod sig;
+pub mod module;
+pub(crate) mod hash;
+pub mod ser;
+pub mod lsp;
+pub mod repl;
+pub mod cli;
+pub mod eval;
+pub mod compile;
+pub mod vm;
+pub mod js;
+
+pub use ast;
+
+pub use ctx::TypeContext;
+pub use tast::{TypedExpression, TypedKind};
+pub use typ::{PolyType, Type, parse_type, TypeParseError};
+pub use incr::QueryCache;
+pub use sig::{Signature, SignatureParseError};
+pub use module::{ModuleLoader, ModuleError};
+pub use typck::{infer, infer_in, infer_program, infer_program_in, infer_program_with, infer_program_typed, infer_tolerant, infer_typed};
+pub use typck::{TypeError, TypedProgram};
+pub use sched::{infer_parallel, Inferred};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::thread;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_send_sync() {
+        assert_send_sync::<ast::Expression>();
+        assert_send_sync::<PolyType>();
+        assert_send_sync::<TypeError>();
+        assert_send_sync::<TypedExpression>();
+    }
+
+    #[test]
+    fn test_parallel_inference() {
+        let programs = [
+            r"\x. x",
+            r"\f. \g. \x. f (g x)",
+            r"let id = \x. x in id id true",
+            r"\x. x x",
+            r"\f. \x. f (f x) true",
+            r"let k = \x. \y. x in k true",
+            r"const (id true)",
+            r"id id const",
+        ];
+        let exprs = programs.map(|src| Arc::new(ast::parse(src).unwrap()));
+
+        // every thread infers in the same environment
+        let env: Arc<[(Arc<str>, PolyType)]> = Arc::new([
+            ("id".into(), parse_type("forall a. a -> a").unwrap()),
+            ("const".into(), parse_type("forall a b. a -> b -> a").unwrap()),
+        ]);
+
+        let infer_with_env = |env: &[(Arc<str>, PolyType)], e: &ast::Expression| {
+            let mut ctx = TypeContext::new();
+            for (name, t) in env {
+                ctx.insert_poly(name.clone(), t);
+            }
+            let t = infer_in(&mut ctx, e);
+            (t.to_string(), ctx.take_errors().iter().map(|err| err.to_string()).collect::<Vec<_>>())
+        };
+
+        let sequential = exprs.iter()
+            .map(|e| infer_with_env(&env, e))
+            .collect::<Vec<_>>();
+
+        let parallel = thread::scope(|s| {
+            let handles = exprs.iter()
+                .map(|e| {
+                    let (env, e) = (env.clone(), e.clone());
+                    s.spawn(move || infer_with_env(&env, &e))
+                })
+                .collect::<Vec<_>>();
+
+            handles.into_iter()
+                .map(|h| h.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        assert_eq!(sequential[6], ("forall a. a -> Bool".to_string(), vec![]));
+        assert_eq!(sequential, parallel);
+    }
+}
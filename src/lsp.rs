@@ -0,0 +1,604 @@
+/*  A language server for programs, speaking JSON-RPC as described in
+ *  https://microsoft.github.io/language-server-protocol/
+ *
+ *  It supports
+ *  - diagnostics for parse and type errors, sent whenever a document changes
+ *  - hover, showing the type of the expression, binder or declaration under
+ *    the cursor
+ *  - go to definition for variables bound by lambdas, lets and declarations
+ *
+ *  Every change re-parses the whole document. Each document keeps the
+ *  ModuleLoader its imports were loaded with and a QueryCache, so that a change
+ *  only re-infers the definitions it affects, as long as the document's
+ *  imports all come first and it doesn't define any modules. Other documents
+ *  are re-inferred as a whole. Since the loader is kept, imported modules are
+ *  only read once while a document is open. The parser's spans and the typed AST both list
+ *  nodes in post-order, which is how types and errors are matched up with the
+ *  source. */
+
+use crate::incr::QueryCache;
+use crate::module::{self, ModuleLoader};
+use crate::sched::Inferred;
+use crate::ser::Json;
+use crate::tast::{TypedExpression, TypedKind};
+use crate::typ::{PolyType, Type};
+use crate::typck::{self, TypeError, TypedProgram};
+
+use ast::{Declaration, ParseError, Program, Span, Spans};
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Reads the body of the next message from 'input', or None at the end of
+/// the input.
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() { break; }
+        if let Some((name, value)) = line.split_once(':') && name.eq_ignore_ascii_case("Content-Length") {
+            len = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let len = len.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    let mut body = vec![0; len];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Writes 'msg' to 'output' with the header that LSP expects.
+pub fn write_message(output: &mut impl Write, msg: &Json) -> io::Result<()> {
+    let body = msg.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
+
+/// The state of the language server: the open documents and whether the
+/// client has asked it to shut down.
+#[derive(Debug, Default)]
+pub struct Server {
+    docs: HashMap<String, Document>,
+    shutdown: bool,
+    exit: Option<i32>,
+}
+
+impl Server {
+    pub fn new() -> Server {
+        Server::default()
+    }
+
+    /// The exit code, once the client has sent the exit notification.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit
+    }
+
+    /// Handles the message 'body' and returns the messages to send back, which
+    /// are the response if it was a request, and any notifications.
+    pub fn handle(&mut self, body: &str) -> Vec<Json> {
+        let msg = match body.parse::<Json>() {
+            Ok(msg) => msg,
+            Err(err) => return vec![error_response(Json::Null, PARSE_ERROR, &err.to_string())],
+        };
+        let Some(method) = msg.get("method").and_then(Json::as_str) else {
+            // a response to a request of ours, which we never send
+            return vec![];
+        };
+        let params = msg.get("params").unwrap_or(&Json::Null);
+
+        match msg.get("id") {
+            Some(id) => vec![match self.request(method, params) {
+                Ok(result) => Json::object([("jsonrpc", "2.0".into()), ("id", id.clone()), ("result", result)]),
+                Err((code, message)) => error_response(id.clone(), code, &message),
+            }],
+            None => self.notification(method, params),
+        }
+    }
+
+    fn request(&mut self, method: &str, params: &Json) -> Result<Json, (i64, String)> {
+        if self.shutdown {
+            return Err((INVALID_REQUEST, "The server is shutting down.".to_string()));
+        }
+
+        match method {
+            "initialize" => Ok(Json::object([
+                ("capabilities", Json::object([
+                    // the whole document is sent on every change
+                    ("textDocumentSync", 1u64.into()),
+                    ("hoverProvider", true.into()),
+                    ("definitionProvider", true.into()),
+                ])),
+                ("serverInfo", Json::object([("name", "algorithm-j".into())])),
+            ])),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Json::Null)
+            }
+            "textDocument/hover" => {
+                let (_, doc, pos) = self.position(params)?;
+                Ok(match doc.hover(pos) {
+                    Some((span, text)) => Json::object([
+                        ("contents", Json::object([("kind", "plaintext".into()), ("value", text.as_str().into())])),
+                        ("range", range(&doc.text, *span)),
+                    ]),
+                    None => Json::Null,
+                })
+            }
+            "textDocument/definition" => {
+                let (uri, doc, pos) = self.position(params)?;
+                let def = doc.defs.iter()
+                    .filter(|(span, _)| span.contains(pos))
+                    .min_by_key(|(span, _)| span.end - span.start);
+                Ok(match def {
+                    Some((_, target)) => Json::object([
+                        ("uri", uri.as_str().into()),
+                        ("range", range(&doc.text, *target)),
+                    ]),
+                    None => Json::Null,
+                })
+            }
+            method => Err((METHOD_NOT_FOUND, format!("Unknown method {method}."))),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let doc = params.get("textDocument");
+        let uri = doc.and_then(|d| d.get("uri")).and_then(Json::as_str).map(str::to_string);
+
+        match (method, uri) {
+            ("exit", _) => {
+                self.exit = Some(if self.shutdown { 0 } else { 1 });
+                vec![]
+            }
+            ("textDocument/didOpen", Some(uri)) => {
+                let text = doc.and_then(|d| d.get("text")).and_then(Json::as_str).unwrap_or_default();
+                let mut doc = Document::new(&uri);
+                doc.analysis = Analysis::new(&uri, text, &mut doc.loader, &mut doc.cache);
+                self.docs.insert(uri.clone(), doc);
+                self.diagnostics(&uri)
+            }
+            // with full sync, the last change holds the whole document
+            ("textDocument/didChange", Some(uri)) => {
+                let changes = params.get("contentChanges").and_then(Json::as_array).unwrap_or_default();
+                let Some(text) = changes.last().and_then(|c| c.get("text")).and_then(Json::as_str) else {
+                    return vec![];
+                };
+                let Some(doc) = self.docs.get_mut(&uri) else { return vec![]; };
+                doc.analysis = Analysis::new(&uri, text, &mut doc.loader, &mut doc.cache);
+                self.diagnostics(&uri)
+            }
+            ("textDocument/didClose", Some(uri)) => {
+                self.docs.remove(&uri);
+                vec![publish_diagnostics(&uri, vec![])]
+            }
+            _ => vec![],
+        }
+    }
+
+    // The document and the byte offset that a text document position refers to
+    fn position(&self, params: &Json) -> Result<(String, &Analysis, usize), (i64, String)> {
+        let uri = params.get("textDocument").and_then(|d| d.get("uri")).and_then(Json::as_str);
+        let pos = params.get("position");
+        let line = pos.and_then(|p| p.get("line")).and_then(Json::as_u64);
+        let character = pos.and_then(|p| p.get("character")).and_then(Json::as_u64);
+
+        let (Some(uri), Some(line), Some(character)) = (uri, line, character) else {
+            return Err((INVALID_PARAMS, "Expected a text document position.".to_string()));
+        };
+        let doc = self.docs.get(uri).ok_or_else(|| (INVALID_PARAMS, format!("{uri} isn't open.")))?;
+        let doc = &doc.analysis;
+        Ok((uri.to_string(), doc, offset(&doc.text, line as usize, character as usize)))
+    }
+
+    /* The diagnostics of document 'uri', preceded by a log message for an
+     * analysis that went wrong */
+    fn diagnostics(&self, uri: &str) -> Vec<Json> {
+        let doc = &self.docs[uri].analysis;
+        let log = doc.failure.iter().map(|message| Json::object([
+            ("jsonrpc", "2.0".into()),
+            ("method", "window/logMessage".into()),
+            // 1 is an error
+            ("params", Json::object([("type", 1u64.into()), ("message", message.as_str().into())])),
+        ]));
+        let diagnostics = doc.errors.iter()
+            .map(|(span, message)| Json::object([
+                ("range", range(&doc.text, *span)),
+                ("severity", 1u64.into()),
+                ("source", "algorithm-j".into()),
+                ("message", message.as_str().into()),
+            ]))
+            .collect();
+        log.chain([publish_diagnostics(uri, diagnostics)]).collect()
+    }
+}
+
+fn error_response(id: Json, code: i64, message: &str) -> Json {
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("id", id),
+        ("error", Json::object([("code", Json::Number(code as f64)), ("message", message.into())])),
+    ])
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Json>) -> Json {
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("method", "textDocument/publishDiagnostics".into()),
+        ("params", Json::object([("uri", uri.into()), ("diagnostics", Json::Array(diagnostics))])),
+    ])
+}
+
+/* LSP positions count lines and UTF-16 code units, while spans are byte
+ * offsets. */
+
+fn position(text: &str, offset: usize) -> Json {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let line = before.matches('\n').count() as u64;
+    let character = before[line_start..].encode_utf16().count() as u64;
+    Json::object([("line", line.into()), ("character", character.into())])
+}
+
+fn range(text: &str, span: Span) -> Json {
+    Json::object([("start", position(text, span.start)), ("end", position(text, span.end))])
+}
+
+fn offset(text: &str, line: usize, character: usize) -> usize {
+    let line_start = text.split_inclusive('\n')
+        .take(line)
+        .map(str::len)
+        .sum::<usize>();
+    let mut units = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if units >= character || c == '\n' {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+/* The path of a file:// uri, which is where the document's imports are
+ * looked for */
+fn uri_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let mut bytes = vec![];
+    let mut rest = path.as_bytes();
+    while let Some((b, tail)) = rest.split_first() {
+        match (b, tail.get(..2).and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok())) {
+            (b'%', Some(decoded)) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(*b);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+/* An open document, along with what is kept around between its changes */
+#[derive(Debug)]
+struct Document {
+    loader: ModuleLoader,
+    cache: QueryCache,
+    analysis: Analysis,
+}
+
+impl Document {
+    fn new(uri: &str) -> Document {
+        let loader = match uri_path(uri).as_ref().and_then(|p| p.parent()) {
+            Some(dir) => ModuleLoader::new([dir]),
+            None => ModuleLoader::default(),
+        };
+        Document { loader, cache: QueryCache::new(), analysis: Analysis::default() }
+    }
+}
+
+/// Everything the server knows about an open document.
+#[derive(Debug, Default)]
+struct Analysis {
+    text: String,
+    // why the analysis is incomplete, if the typed program didn't line up with
+    // the spans, which is a bug
+    failure: Option<String>,
+    errors: Vec<(Span, String)>,
+    hovers: Vec<(Span, String)>,
+    // references to the span of the binder they refer to
+    defs: Vec<(Span, Span)>,
+}
+
+impl Analysis {
+    fn new(uri: &str, text: &str, loader: &mut ModuleLoader, cache: &mut QueryCache) -> Analysis {
+        let (program, spans, parse_errors) = ast::parse_program_spanned(text);
+        let typed = infer(loader, cache, &program);
+        let exprs = typed.exprs();
+
+        let mut walker = Walker {
+            spans: &spans,
+            exprs: &exprs,
+            node: 0,
+            decl: 0,
+            expr: 0,
+            scope: vec![],
+            imports: vec![],
+            analysis: Analysis { text: text.to_string(), ..Analysis::default() },
+        };
+        walker.decls(&program.decls);
+        let visited = (walker.decl, walker.expr, walker.node);
+        let expected = (spans.decls.len(), exprs.len(), spans.nodes.len());
+        let Walker { imports, mut analysis, .. } = walker;
+        if visited != expected {
+            analysis.failure = Some(format!(
+                "The typed program doesn't line up with the spans of {uri}: visited {visited:?} \
+                 (declarations, lets, nodes) out of {expected:?}.",
+            ));
+        }
+
+        for err in &parse_errors {
+            let pos = match err {
+                ParseError::UnexpectedToken { pos, .. } | ParseError::InvalidToken { pos, .. } => *pos,
+            };
+            let end = text[pos.min(text.len())..].chars().next().map_or(pos, |c| pos + c.len_utf8());
+            analysis.errors.push((Span { start: pos, end }, err.to_string()));
+        }
+        for (err, node) in typed.errors() {
+            let span = match (node, err) {
+                (Some(node), _) => spans.nodes.get(node).map(|n| n.span),
+                (None, TypeError::Import(err)) => imports.iter()
+                    .find(|(module, _)| module == err.module())
+                    .map(|(_, span)| *span),
+                (None, _) => None,
+            };
+            analysis.errors.push((span.unwrap_or_default(), err.to_string()));
+        }
+        analysis
+    }
+
+    /* The innermost hover that 'pos' is in */
+    fn hover(&self, pos: usize) -> Option<&(Span, String)> {
+        self.hovers.iter()
+            .filter(|(span, _)| span.contains(pos))
+            .min_by_key(|(span, _)| span.end - span.start)
+    }
+}
+
+/* What inferring a document gives, either inferred as a whole or a definition
+ * at a time */
+enum Typed {
+    Program(TypedProgram),
+    Definitions {
+        defs: Vec<Arc<Inferred>>,
+        imports: Vec<TypeError>,
+    },
+}
+
+impl Typed {
+    fn exprs(&self) -> Vec<&TypedExpression> {
+        match self {
+            Typed::Program(typed) => typed.exprs.iter().collect(),
+            Typed::Definitions { defs, .. } => defs.iter().map(|d| &d.typed).collect(),
+        }
+    }
+
+    /* Every error along with the node it was found at, counting across the
+     * whole document */
+    fn errors(&self) -> Vec<(&TypeError, Option<usize>)> {
+        match self {
+            Typed::Program(typed) => typed.errors.iter().map(|(err, node)| (err, *node)).collect(),
+            Typed::Definitions { defs, imports } => {
+                let mut errors = imports.iter().map(|err| (err, None)).collect::<Vec<_>>();
+                let mut first = 0;
+                for def in defs {
+                    errors.extend(def.errors.iter().map(|(err, node)| (err, node.map(|n| first + n))));
+                    first += size(&def.typed);
+                }
+                errors
+            }
+        }
+    }
+}
+
+/* Infers a definition at a time through 'cache' if the imports all come
+ * first and there are no modules, since a module's definitions see each other
+ * unqualified and an import only affects the definitions after it. */
+fn infer(loader: &mut ModuleLoader, cache: &mut QueryCache, program: &Program) -> Typed {
+    let lets = program.decls.iter().position(|decl| !matches!(decl, Declaration::Import { .. }));
+    let incremental = program.decls[lets.unwrap_or(program.decls.len())..].iter().all(|decl| matches!(decl, Declaration::Let { .. }));
+    if !incremental {
+        return Typed::Program(typck::infer_program_typed(loader, program));
+    }
+
+    let mut env = vec![];
+    let mut imports = vec![];
+    for module in program.decls.iter().filter_map(|decl| match decl {
+        Declaration::Import { module } => Some(module),
+        _ => None,
+    }) {
+        match loader.load(module) {
+            Ok(sig) => env.extend(sig.items.iter().map(|(name, t)| (module::qualify(module, name), t.clone()))),
+            Err(err) => imports.push(TypeError::Import(err)),
+        }
+    }
+    Typed::Definitions { defs: cache.infer(&env, &program.definitions()), imports }
+}
+
+/* Walks the declarations and the typed ASTs in the same order the parser
+ * recorded their spans in, matching up each node with its span. The two always
+ * line up, which Analysis::new checks, logging a failure if they don't; the
+ * walker stops at whatever is missing instead of panicking. */
+struct Walker<'a> {
+    spans: &'a Spans,
+    exprs: &'a [&'a TypedExpression],
+    // the next node, declaration and let expression to be visited
+    node: usize,
+    decl: usize,
+    expr: usize,
+    // the binders in scope along with where their name is
+    scope: Vec<(Arc<str>, Span)>,
+    imports: Vec<(Arc<str>, Span)>,
+    analysis: Analysis,
+}
+
+impl Walker<'_> {
+    fn decls(&mut self, decls: &[Declaration]) {
+        for decl in decls {
+            let Some(span) = self.spans.decls.get(self.decl).copied() else { return; };
+            let name = span.name.unwrap_or(span.span);
+            self.decl += 1;
+
+            match decl {
+                Declaration::Let { name: sym, .. } => {
+                    let Some(e) = self.exprs.get(self.expr) else { return; };
+                    self.expr += 1;
+                    self.expr_node(e);
+
+                    let poly = PolyType::generalize_all(e.typ.clone());
+                    self.analysis.hovers.push((name, format!("{sym} : {poly}")));
+                    self.scope.push((sym.clone(), name));
+                }
+                Declaration::Import { module } => {
+                    self.imports.push((module.clone(), span.span));
+                }
+                Declaration::Module { name: m, decls } => {
+                    let outer = self.scope.len();
+                    self.decls(decls);
+                    let exports = self.scope.split_off(outer);
+                    self.scope.extend(exports.into_iter().map(|(sym, span)| (module::qualify(m, &sym), span)));
+                }
+            }
+        }
+    }
+
+    fn expr_node(&mut self, e: &TypedExpression) {
+        let index = self.node + size(e) - 1;
+        let Some(span) = self.spans.nodes.get(index).copied() else { return; };
+        let name = span.name.unwrap_or(span.span);
+
+        let hover = match &e.kind {
+            TypedKind::Var { name: sym } => {
+                if let Some((_, def)) = self.scope.iter().rev().find(|(s, _)| s == sym) {
+                    self.analysis.defs.push((span.span, *def));
+                }
+                self.node += 1;
+                format!("{sym} : {}", e.typ)
+            }
+            TypedKind::App { f, e: x } => {
+                self.expr_node(f);
+                self.expr_node(x);
+                self.node += 1;
+                e.typ.to_string()
+            }
+            TypedKind::Abs { name: sym, e: body } => {
+                if let Type::Func { l, .. } = &*e.typ {
+                    self.analysis.hovers.push((name, format!("{sym} : {l}")));
+                }
+                self.scope.push((sym.clone(), name));
+                self.expr_node(body);
+                self.scope.pop();
+                self.node += 1;
+                e.typ.to_string()
+            }
+            TypedKind::Let { name: sym, poly, e0, e1 } => {
+                self.analysis.hovers.push((name, format!("{sym} : {poly}")));
+                self.expr_node(e0);
+                self.scope.push((sym.clone(), name));
+                self.expr_node(e1);
+                self.scope.pop();
+                self.node += 1;
+                e.typ.to_string()
+            }
+            TypedKind::True | TypedKind::False | TypedKind::Error => {
+                self.node += 1;
+                e.typ.to_string()
+            }
+        };
+        self.analysis.hovers.push((span.span, hover));
+    }
+}
+
+fn size(e: &TypedExpression) -> usize {
+    match &e.kind {
+        TypedKind::App { f, e } => size(f) + size(e) + 1,
+        TypedKind::Abs { e, .. } => size(e) + 1,
+        TypedKind::Let { e0, e1, .. } => size(e0) + size(e1) + 1,
+        TypedKind::Var { .. } | TypedKind::True | TypedKind::False | TypedKind::Error => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_positions() {
+        let text = "let a = true\nlet 😀b = é a\n";
+        let pos = |offset| position(text, offset).to_string();
+        assert_eq!(pos(0), r#"{"line":0,"character":0}"#);
+        assert_eq!(pos(text.find('b').unwrap()), r#"{"line":1,"character":6}"#);
+        assert_eq!(offset(text, 1, 6), text.find('b').unwrap());
+        assert_eq!(offset(text, 1, 100), text.len() - 1);
+        assert_eq!(offset(text, 5, 0), text.len());
+        assert_eq!(uri_path("file:///tmp/a%20b.lam"), Some(PathBuf::from("/tmp/a b.lam")));
+    }
+
+    #[test]
+    fn test_parse_errors_in_modules() {
+        let text = "module M = struct\n  let a = (true\n  let b = \\x. x\nend\nlet c = M.b ) M.b\nlet d = M.b true\n";
+        let uri = "file:///nonexistent/main.lam";
+        let mut doc = Document::new(uri);
+        let analysis = Analysis::new(uri, text, &mut doc.loader, &mut doc.cache);
+        let hover = |needle: &str| analysis.hover(text.find(needle).unwrap()).map(|(_, h)| h.as_str());
+
+        assert_eq!(hover("b ="), Some("b : forall a. a -> a"));
+        assert_eq!(hover("d ="), Some("d : Bool"));
+        assert_eq!(hover("M.b true"), Some("M.b : Bool -> Bool"));
+        assert_eq!(analysis.errors.len(), 2);
+    }
+
+    #[test]
+    fn test_changes() {
+        let uri = "file:///nonexistent/main.lam";
+        let notify = |method: &str, doc: Json| Json::object([
+            ("jsonrpc", "2.0".into()),
+            ("method", method.into()),
+            ("params", Json::object([("textDocument", doc.clone()), ("contentChanges", Json::Array(vec![doc]))])),
+        ]).to_string();
+        let doc = |text: &str| Json::object([("uri", uri.into()), ("text", text.into())]);
+
+        let mut server = Server::new();
+        server.handle(&notify("textDocument/didOpen", doc("let a = true\nlet b = \\x. x\nlet c = a a\n")));
+        assert_eq!(server.docs[uri].cache.reinferred(), [0, 1, 2]);
+
+        // only the definition that changed is inferred again, and its error is
+        // still found at the right place
+        let replies = server.handle(&notify("textDocument/didChange", doc("let a = true\nlet b = \\y. y\nlet c = a a\n")));
+        assert_eq!(server.docs[uri].cache.reinferred(), [1]);
+        let errors = &server.docs[uri].analysis.errors;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, Span { start: 35, end: 38 });
+        assert_eq!(replies.len(), 1);
+
+        // an analysis that went wrong is logged along with the diagnostics
+        server.docs.get_mut(uri).unwrap().analysis.failure = Some("oops".to_string());
+        let replies = server.diagnostics(uri);
+        assert_eq!(replies[0].get("method").and_then(Json::as_str), Some("window/logMessage"));
+        assert_eq!(replies[1].get("method").and_then(Json::as_str), Some("textDocument/publishDiagnostics"));
+    }
+
+}
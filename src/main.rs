@@ -1,37 +1,64 @@
-mod typ;
-
-mod ctx;
-use ctx::TypeContext;
-
-mod typck;
+use algorithm_j::cli::{self, Command};
+use algorithm_j::repl::{self, Reply, Session};
 
 use std::io;
 use std::io::Write;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let command = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            return ExitCode::from(cli::EXIT_USAGE);
+        }
+    };
+    if command == Command::Repl {
+        repl();
+        return ExitCode::SUCCESS;
+    }
+
+    match cli::run(&command, &mut io::stdin().lock(), &mut io::stdout().lock(), &mut io::stderr().lock()) {
+        Ok(code) => ExitCode::from(code),
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::from(cli::EXIT_USAGE)
+        }
+    }
+}
 
-fn main() {
+fn repl() {
+    let mut session = Session::default();
     let mut buf = String::new();
     let stdin = io::stdin();
     loop {
-        print!(">> ");
+        print!("{}", if buf.is_empty() { ">> " } else { ".. " });
         let _ = io::stdout().flush();
-        if let Err(e) = stdin.read_line(&mut buf) {
-            eprintln!("{}", e); return;
-        }
 
-        let e = match ast::parse(buf.as_str()) {
-            Ok(e)  => e,
-            Err(e) => {
-                eprintln!("{}", e);
-                buf.clear();
-                continue;
-            },
+        let mut line = String::new();
+        let eof = match stdin.read_line(&mut line) {
+            Ok(n) => n == 0,
+            Err(e) => { eprintln!("{}", e); return; }
         };
+        buf.push_str(&line);
 
-        match typck::infer(e) {
-            Ok(t)  => println!("{}\n", t),
-            Err(e) => eprintln!("{}", e),
+        // a blank line ends an input that's still incomplete, errors and all
+        if !eof && !line.trim().is_empty() && repl::is_incomplete(&buf) {
+            continue;
         }
 
+        match session.handle(&buf) {
+            Reply::Output(out) => print!("{}", out),
+            Reply::Errors(errors) => for e in errors {
+                eprintln!("{}", e);
+            },
+            Reply::Quit => return,
+        }
         buf.clear();
+
+        if eof {
+            println!();
+            return;
+        }
     }
 }
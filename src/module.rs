@@ -0,0 +1,468 @@
+/*  Loads the modules that programs import.
+ *
+ *  A module is a file holding a program, and `import Foo.Bar` looks for
+ *  Foo/Bar.lam in each of the loader's search paths in turn. Whatever the
+ *  module's program exports becomes its signature, which the importing program
+ *  sees as qualified names like `Foo.Bar.name`.
+ *
+ *  If there's a signature file Foo/Bar.lami next to the module, the module is
+ *  checked against it and only exports what it declares.
+ *
+ *  Every module is inferred once per loader, no matter how many programs
+ *  import it. With a cache directory, signatures are also written to disk,
+ *  keyed on a hash of the module's source, its signature file and the
+ *  signatures of everything it imports, so that later runs don't have to
+ *  infer a module again until one of those changes. Besides that key, a cache
+ *  entry records the module's name and a hash of its source alone, which have
+ *  to match as well, so that a collision in the key can't pass one module's
+ *  signature off as another's. */
+
+use crate::hash::ContentHasher;
+use crate::sig::{Signature, SignatureParseError};
+use crate::typck::{self, TypeError, TypedProgram};
+
+use ast::{Declaration, ParseError, Program};
+use thiserror::Error;
+
+use std::collections::HashMap;
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The extension of the files that modules are loaded from.
+pub const EXTENSION: &str = "lam";
+
+/// The extension of signature files.
+pub const SIG_EXTENSION: &str = "lami";
+
+/// The extension of cached signatures.
+pub const CACHE_EXTENSION: &str = "lamc";
+
+/// An error found while loading an imported module.
+#[derive(Debug, Clone, Error)]
+pub enum ModuleError {
+    #[error("Module {name} not found, looked for {}.",
+        searched.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(", ")
+    )]
+    NotFound { name: Arc<str>, searched: Vec<PathBuf> },
+    #[error("Module {name} couldn't be read from {}: {err}", path.display())]
+    Io { name: Arc<str>, path: PathBuf, err: Arc<std::io::Error> },
+    #[error("Module {name} imports itself: {}.", cycle.join(" -> "))]
+    Cyclic { name: Arc<str>, cycle: Vec<Arc<str>> },
+    #[error("Module {name} doesn't parse: {}",
+        errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(" ")
+    )]
+    Parse { name: Arc<str>, errors: Vec<ParseError> },
+    #[error("The signature of module {name} in {} is invalid: {err}", path.display())]
+    Signature { name: Arc<str>, path: PathBuf, err: SignatureParseError },
+    #[error("Module {name} doesn't type check: {}",
+        errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(" ")
+    )]
+    Type { name: Arc<str>, errors: Vec<TypeError> },
+}
+
+impl ModuleError {
+    /// The module that failed to load.
+    pub fn module(&self) -> &Arc<str> {
+        match self {
+            ModuleError::NotFound { name, .. }
+            | ModuleError::Io { name, .. }
+            | ModuleError::Cyclic { name, .. }
+            | ModuleError::Parse { name, .. }
+            | ModuleError::Signature { name, .. }
+            | ModuleError::Type { name, .. } => name,
+        }
+    }
+}
+
+/// Finds, parses and infers imported modules, and remembers the signatures of
+/// the ones it has already loaded, or why they failed to load.
+#[derive(Debug, Default)]
+pub struct ModuleLoader {
+    paths: Vec<PathBuf>,
+    cache: Option<PathBuf>,
+    loaded: HashMap<Arc<str>, Result<Signature, ModuleError>>,
+    // the modules being loaded right now, innermost last
+    loading: Vec<Arc<str>>,
+    // the programs of the modules that loaded, as they were read
+    programs: HashMap<Arc<str>, Arc<Program>>,
+    typed: HashMap<Arc<str>, Arc<TypedModule>>,
+}
+
+/// A module's program along with its typed AST, see ModuleLoader::load_typed.
+#[derive(Debug)]
+pub struct TypedModule {
+    pub program: Arc<Program>,
+    pub typed: TypedProgram,
+}
+
+/// The name that 'name' is known by outside of 'module'.
+pub fn qualify(module: &str, name: &str) -> Arc<str> {
+    format!("{module}.{name}").into()
+}
+
+impl ModuleLoader {
+    /// A loader that searches for modules in 'paths', in order.
+    pub fn new(paths: impl IntoIterator<Item = impl Into<PathBuf>>) -> ModuleLoader {
+        ModuleLoader {
+            paths: paths.into_iter().map(Into::into).collect(),
+            ..ModuleLoader::default()
+        }
+    }
+
+    /// Also searches 'dir' for modules, after the paths it already searches.
+    /// Modules that were loaded already stay loaded.
+    pub fn add_path(&mut self, dir: impl Into<PathBuf>) {
+        let dir = dir.into();
+        if !self.paths.contains(&dir) {
+            self.paths.push(dir);
+        }
+    }
+
+    /// Writes the signatures of loaded modules to 'dir', and reads them back
+    /// instead of inferring modules that haven't changed since.
+    pub fn with_cache(mut self, dir: impl Into<PathBuf>) -> ModuleLoader {
+        self.cache = Some(dir.into());
+        self
+    }
+
+    /// The file that module 'name' gets loaded from.
+    pub fn find(&self, name: &str) -> Result<PathBuf, ModuleError> {
+        let file = name.split('.').collect::<PathBuf>().with_extension(EXTENSION);
+        let searched = self.paths.iter()
+            .map(|dir| dir.join(&file))
+            .collect::<Vec<_>>();
+
+        match searched.iter().find(|path| path.is_file()) {
+            Some(path) => Ok(path.clone()),
+            None => Err(ModuleError::NotFound { name: name.into(), searched }),
+        }
+    }
+
+    /// Loads module 'name', which has to parse and type check without errors,
+    /// and returns its signature. Each module is only loaded once, later calls
+    /// return the same signature or error.
+    pub fn load(&mut self, name: &Arc<str>) -> Result<Signature, ModuleError> {
+        if let Some(result) = self.loaded.get(name) {
+            return result.clone();
+        }
+        if let Some(i) = self.loading.iter().position(|n| n == name) {
+            let mut cycle = self.loading[i..].to_vec();
+            cycle.push(name.clone());
+            return Err(ModuleError::Cyclic { name: name.clone(), cycle });
+        }
+
+        let result = self.load_file(name);
+        self.loaded.insert(name.clone(), result.clone());
+        result
+    }
+
+    /// Loads module 'name' like load, and also returns its program and typed
+    /// AST, for backends that need to run the module rather than just check
+    /// against it. The program is the one load read and checked, so a module
+    /// whose file changed since then still runs as it was checked.
+    pub fn load_typed(&mut self, name: &Arc<str>) -> Result<Arc<TypedModule>, ModuleError> {
+        self.load(name)?;
+        if let Some(module) = self.typed.get(name) {
+            return Ok(module.clone());
+        }
+
+        let program = self.programs[name].clone();
+        let typed = typck::infer_program_typed(self, &program);
+        if !typed.errors.is_empty() {
+            let errors = typed.errors.into_iter().map(|(err, _)| err).collect();
+            return Err(ModuleError::Type { name: name.clone(), errors });
+        }
+        let module = Arc::new(TypedModule { program, typed });
+        self.typed.insert(name.clone(), module.clone());
+        Ok(module)
+    }
+
+    fn load_file(&mut self, name: &Arc<str>) -> Result<Signature, ModuleError> {
+        let path = self.find(name)?;
+        let src = read(name, &path)?;
+        let sig_path = path.with_extension(SIG_EXTENSION);
+        let declared = match sig_path.is_file() {
+            true => Some(read(name, &sig_path)?),
+            false => None,
+        };
+
+        let (program, errors) = ast::parse_program_tolerant(&src);
+        if !errors.is_empty() {
+            return Err(ModuleError::Parse { name: name.clone(), errors });
+        }
+
+        self.loading.push(name.clone());
+        let sig = self.load_program(name, &program, &src, declared.as_deref(), &sig_path);
+        self.loading.pop();
+
+        if sig.is_ok() {
+            self.programs.insert(name.clone(), Arc::new(program));
+        }
+        sig
+    }
+
+    fn load_program(
+        &mut self,
+        name: &Arc<str>,
+        program: &Program,
+        src: &str,
+        declared: Option<&str>,
+        sig_path: &Path,
+    ) -> Result<Signature, ModuleError> {
+        let mut h = ContentHasher::default();
+        h.write_str(src);
+        h.write_str(declared.unwrap_or_default());
+        let source = h.finish();
+
+        let declared = declared
+            .map(|text| text.parse::<Signature>())
+            .transpose()
+            .map_err(|err| ModuleError::Signature { name: name.clone(), path: sig_path.into(), err })?;
+
+        /* The imports are loaded up front since their signatures are part of
+         * the cache key. If one of them fails the cache is skipped, and the
+         * failure gets reported by inference, which finds it in 'loaded'
+         * rather than trying to load the module again. */
+        let mut complete = true;
+        for module in imports(&program.decls) {
+            match self.load(&module) {
+                Ok(sig) => {
+                    h.write_str(&module);
+                    h.write_str(&sig.to_string());
+                }
+                Err(_) => complete = false,
+            }
+        }
+        let key = complete.then(|| CacheKey { source, deps: h.finish() });
+
+        if let Some(sig) = key.as_ref().and_then(|key| self.read_cache(name, key)) {
+            return Ok(sig);
+        }
+
+        let (inferred, mut errors) = typck::infer_program_with(self, program);
+        let sig = match declared {
+            Some(declared) => {
+                errors.extend(declared.check(&inferred));
+                declared
+            }
+            None => inferred,
+        };
+        if !errors.is_empty() {
+            return Err(ModuleError::Type { name: name.clone(), errors });
+        }
+
+        if let Some(key) = &key {
+            self.write_cache(name, key, &sig);
+        }
+        Ok(sig)
+    }
+
+    /// Where the signature of module 'name' is cached, if there's a cache.
+    pub fn cache_path(&self, name: &str) -> Option<PathBuf> {
+        self.cache.as_ref().map(|dir| dir.join(format!("{name}.{CACHE_EXTENSION}")))
+    }
+
+    /* Cached signatures start with a header of comment lines, which the
+     * signature parser skips. A cache entry that can't be read, is out of date
+     * or belongs to some other module is ignored. */
+    fn read_cache(&self, name: &str, key: &CacheKey) -> Option<Signature> {
+        let text = std::fs::read_to_string(self.cache_path(name)?).ok()?;
+        let sig = text.strip_prefix(&key.header(name))?;
+        sig.parse().ok()
+    }
+
+    /* The cache only saves time, so failing to write it isn't an error. The
+     * entry is written to a temporary file first and then renamed into place,
+     * so that a loader reading it at the same time never sees half of it. */
+    fn write_cache(&self, name: &str, key: &CacheKey, sig: &Signature) {
+        let (Some(dir), Some(path)) = (&self.cache, self.cache_path(name)) else { return; };
+        let tmp = dir.join(format!("{name}.{CACHE_EXTENSION}.{}.tmp", std::process::id()));
+        let written = std::fs::create_dir_all(dir)
+            .and_then(|_| std::fs::write(&tmp, format!("{}{sig}", key.header(name))))
+            .and_then(|_| std::fs::rename(&tmp, path));
+        if written.is_err() {
+            let _ = std::fs::remove_file(&tmp);
+        }
+    }
+}
+
+/* What a cached signature is valid for. 'source' only covers the module's own
+ * source and signature file, 'deps' also covers the signatures of its imports. */
+struct CacheKey {
+    source: u64,
+    deps: u64,
+}
+
+impl CacheKey {
+    fn header(&self, name: &str) -> String {
+        format!("# module {name}\n# source {:016x}\n# hash {:016x}\n", self.source, self.deps)
+    }
+}
+
+fn read(name: &Arc<str>, path: &Path) -> Result<String, ModuleError> {
+    std::fs::read_to_string(path)
+        .map_err(|err| ModuleError::Io { name: name.clone(), path: path.into(), err: err.into() })
+}
+
+/* Every module imported by 'decls', including from inside nested modules */
+fn imports(decls: &[Declaration]) -> Vec<Arc<str>> {
+    decls.iter()
+        .flat_map(|decl| match decl {
+            Declaration::Import { module } => vec![module.clone()],
+            Declaration::Module { decls, .. } => imports(decls),
+            Declaration::Let { .. } => vec![],
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::typ::parse_type;
+
+    use std::fs;
+    use std::ops::Deref;
+
+    /* A temporary directory that is removed again once the test is done with
+     * it, whether or not the test passed */
+    pub(crate) struct TempDir(PathBuf);
+
+    impl Deref for TempDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    pub(crate) fn write_modules(dir: &str, modules: &[(&str, &str)]) -> TempDir {
+        let root = TempDir(std::env::temp_dir().join(format!("algorithm-j-{dir}-{}", std::process::id())));
+        for (path, src) in modules {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, src).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn test_imports() {
+        let root = write_modules("imports", &[
+            ("Prelude.lam", "let id = \\x. x\nlet k = \\x. \\y. x"),
+            ("Data/Bool.lam", "import Prelude\nlet t = Prelude.k true\nmodule Not = struct let f = false end"),
+        ]);
+        let program = ast::parse_program(
+            "import Data.Bool\nmodule M = struct\n  import Prelude\n  let id = Prelude.id\nend\nlet a = M.id Data.Bool.Not.f"
+        ).unwrap();
+
+        let mut loader = ModuleLoader::new([&*root]);
+        let (sig, errors) = typck::infer_program_with(&mut loader, &program);
+        assert!(errors.is_empty(), "{errors:?}");
+        assert!(sig.lookup("M.id").unwrap().alpha_eq(&parse_type("forall a. a -> a").unwrap()));
+        assert_eq!(sig.lookup("a"), Some(&parse_type("Bool").unwrap()));
+
+        let bool_sig = loader.load(&"Data.Bool".into()).unwrap();
+        assert_eq!(bool_sig.to_string(), "t : forall a. a -> Bool\nNot.f : Bool\n");
+    }
+
+    #[test]
+    fn test_signature_files() {
+        let root = write_modules("signature-files", &[
+            ("Good.lam", "let id = \\x. x\nlet helper = true"),
+            ("Good.lami", "# only id is exported\nid : Bool -> Bool\n"),
+            ("Bad.lam", "let id = \\x. true"),
+            ("Bad.lami", "id : forall a. a -> a\nmissing : Bool\n"),
+        ]);
+        let mut loader = ModuleLoader::new([&*root]);
+
+        let sig = loader.load(&"Good".into()).unwrap();
+        assert_eq!(sig.to_string(), "id : Bool -> Bool\n");
+
+        let Err(ModuleError::Type { errors, .. }) = loader.load(&"Bad".into()) else {
+            panic!("Bad shouldn't match its signature");
+        };
+        assert!(matches!(&errors[..], [TypeError::SignatureMismatch { .. }, TypeError::NotDefined { .. }]));
+    }
+
+    #[test]
+    fn test_cache() {
+        let root = write_modules("cache", &[
+            ("A.lam", "let id = \\x. x"),
+            ("B.lam", "import A\nlet b = A.id true"),
+        ]);
+        let cache = root.join("cache");
+        let load = |name: &str| ModuleLoader::new([&*root]).with_cache(&cache).load(&name.into()).unwrap();
+
+        assert_eq!(load("B").to_string(), "b : Bool\n");
+        let cached = fs::read_to_string(cache.join("B.lamc")).unwrap();
+        assert!(cached.starts_with("# module B\n# source ") && cached.ends_with("\nb : Bool\n"), "{cached}");
+        assert_eq!(fs::read_dir(&cache).unwrap().count(), 2);
+
+        // an up to date cache entry is used as is, without inferring B again
+        fs::write(cache.join("B.lamc"), cached.replace("b : Bool", "b : Bool -> Bool")).unwrap();
+        assert_eq!(load("B").to_string(), "b : Bool -> Bool\n");
+
+        // an entry that was written for another module isn't used, even if
+        // its hash matches
+        fs::write(cache.join("B.lamc"), cached.replace("# module B", "# module C").replace("b : Bool", "c : Bool")).unwrap();
+        assert_eq!(load("B").to_string(), "b : Bool\n");
+        assert_eq!(fs::read_to_string(cache.join("B.lamc")).unwrap(), cached);
+
+        // changing a dependency invalidates the entry
+        fs::write(root.join("A.lam"), "let id = \\x. \\y. y").unwrap();
+        assert_eq!(load("B").to_string(), "b : forall a. a -> a\n");
+    }
+
+    #[test]
+    fn test_failed_loads() {
+        let root = write_modules("failed-loads", &[
+            ("Bad.lam", "let a = (true"),
+            ("Main.lam", "import Bad\nlet b = Bad.a"),
+        ]);
+        let mut loader = ModuleLoader::new([&*root]);
+        assert!(matches!(loader.load(&"Main".into()), Err(ModuleError::Type { .. })));
+
+        // the failure is remembered rather than Bad being read again
+        fs::write(root.join("Bad.lam"), "let a = true").unwrap();
+        assert!(matches!(loader.load(&"Bad".into()), Err(ModuleError::Parse { .. })));
+        assert!(matches!(loader.load(&"Main".into()), Err(ModuleError::Type { .. })));
+        assert!(ModuleLoader::new([&*root]).load(&"Main".into()).is_ok());
+    }
+
+    #[test]
+    fn test_load_typed() {
+        let root = write_modules("load-typed", &[("A.lam", "let a = true")]);
+        let mut loader = ModuleLoader::new([&*root]);
+        let program = ast::parse_program("import A\nlet b = A.a").unwrap();
+        assert!(typck::infer_program_with(&mut loader, &program).1.is_empty());
+
+        // the backends get the program that was checked, not what's on disk now
+        fs::write(root.join("A.lam"), "let a = true true").unwrap();
+        let module = loader.load_typed(&"A".into()).unwrap();
+        assert_eq!(module.program.to_string(), "let a = true\n");
+        assert_eq!(module.typed.exprs.len(), 1);
+        let values = crate::eval::eval_program(&mut loader, &program).unwrap();
+        assert_eq!(values[0].1.to_string(), "true");
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let root = write_modules("import-errors", &[
+            ("A.lam", "import B\nlet a = true"),
+            ("B.lam", "import A\nlet b = true"),
+        ]);
+        let program = ast::parse_program("import A\nimport Missing\nlet x = Missing.x").unwrap();
+
+        let (_, errors) = typck::infer_program_with(&mut ModuleLoader::new([&*root]), &program);
+        assert!(errors[0].to_string().contains("Module A imports itself: A -> B -> A."), "{}", errors[0]);
+        assert!(matches!(&errors[1], TypeError::Import(ModuleError::NotFound { .. })));
+        assert!(matches!(&errors[2], TypeError::VarNotFound { .. }));
+    }
+}
@@ -0,0 +1,290 @@
+/*  The state behind the REPL in main.rs, kept apart from the terminal so that
+ *  it can be tested.
+ *
+ *  Every input is either an expression, whose type gets printed, or one or
+ *  more declarations like in a program file. Declarations are evaluated and
+ *  stay bound for the rest of the session, unless they don't type check, in
+ *  which case none of them are kept. Inputs starting with ':' are commands,
+ *  see HELP. */
+
+use crate::ctx::TypeContext;
+use crate::eval::{self, Env};
+use crate::module::ModuleLoader;
+use crate::typ::PolyType;
+use crate::typck;
+
+use ast::{Expression, ParseError, Program, TokenKind};
+
+use std::path::Path;
+
+const HELP: &str = "\
+<expression>      print the type of the expression
+let x = e         bind x for the rest of the session, as do import and module
+:type <expr>      print the type of the expression, also :t
+:eval <expr>      print the value and the type of the expression, also :e
+:env              list the bindings in scope and their types
+:reset            forget every binding
+:load <file>      bind the declarations in the file, also :l
+:history          list the inputs so far
+:help             print this, also :h or :?
+:quit             leave, as does end of input, also :q";
+
+/// What the REPL should print in response to an input.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Reply {
+    /// Output for stdout, which may be empty
+    Output(String),
+    /// Errors for stderr, one per line
+    Errors(Vec<String>),
+    Quit,
+}
+
+/// A REPL session, which remembers the bindings made so far.
+pub struct Session {
+    ctx: TypeContext,
+    // the values of everything bound in 'ctx'
+    env: Env,
+    loader: ModuleLoader,
+    history: Vec<String>,
+}
+
+impl Default for Session {
+    fn default() -> Session {
+        Session::new(ModuleLoader::new(["."]))
+    }
+}
+
+enum Input {
+    Decls(Program),
+    Expr(Expression),
+    Invalid(Vec<ParseError>),
+}
+
+/* Whether 'src' is a list of declarations or an expression. When it's
+ * neither, the errors are taken from whichever of the two parsed furthest,
+ * which is the one the user most likely meant. */
+fn parse_input(src: &str) -> Input {
+    let (program, program_errors) = ast::parse_program_tolerant(src);
+    if program_errors.is_empty() {
+        return Input::Decls(program);
+    }
+    let (e, expr_errors) = ast::parse_tolerant(src);
+    if expr_errors.is_empty() {
+        return Input::Expr(e);
+    }
+
+    let pos = |errors: &[ParseError]| match errors.first() {
+        Some(ParseError::UnexpectedToken { pos, .. } | ParseError::InvalidToken { pos, .. }) => *pos,
+        None => 0,
+    };
+    match pos(&program_errors) >= pos(&expr_errors) {
+        true => Input::Invalid(program_errors),
+        false => Input::Invalid(expr_errors),
+    }
+}
+
+/// Whether 'src' is the start of an expression or declaration that goes on
+/// past the end of it, so the REPL should read another line before handling it.
+pub fn is_incomplete(src: &str) -> bool {
+    if src.trim_start().starts_with(':') {
+        return false;
+    }
+    match parse_input(src) {
+        Input::Invalid(errors) => matches!(
+            errors.first(),
+            Some(ParseError::UnexpectedToken { unexpected: TokenKind::EOF, .. })
+        ),
+        _ => false,
+    }
+}
+
+impl Session {
+    /// A session that loads the modules imported from it with 'loader'.
+    pub fn new(loader: ModuleLoader) -> Session {
+        Session { ctx: TypeContext::new(), env: Env::new(), loader, history: vec![] }
+    }
+
+    /// Handles one input, which may span several lines.
+    pub fn handle(&mut self, input: &str) -> Reply {
+        let input = input.trim();
+        if input.is_empty() {
+            return Reply::Output(String::new());
+        }
+        self.history.push(input.to_string());
+
+        let Some(command) = input.strip_prefix(':') else {
+            return match parse_input(input) {
+                Input::Decls(program) => declare(&mut self.ctx, &mut self.env, &mut self.loader, &program),
+                Input::Expr(e) => match self.infer(&e) {
+                    Ok(t) => Reply::Output(format!("{t}\n")),
+                    Err(reply) => reply,
+                },
+                Input::Invalid(errors) => Reply::Errors(errors.iter().map(|e| e.to_string()).collect()),
+            };
+        };
+
+        let (command, arg) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+        let arg = arg.trim();
+        match command {
+            "type" | "t" => match ast::parse_tolerant(arg) {
+                (e, errors) if errors.is_empty() => match self.infer(&e) {
+                    Ok(t) => Reply::Output(format!("{t}\n")),
+                    Err(reply) => reply,
+                },
+                (_, errors) => Reply::Errors(errors.iter().map(|e| e.to_string()).collect()),
+            },
+            "eval" | "e" => match ast::parse_tolerant(arg) {
+                (e, errors) if errors.is_empty() => match self.infer(&e) {
+                    Ok(t) => match eval::eval_in(&self.env, &e) {
+                        Ok(v) => Reply::Output(format!("{v} : {t}\n")),
+                        Err(err) => Reply::Errors(vec![err.to_string()]),
+                    },
+                    Err(reply) => reply,
+                },
+                (_, errors) => Reply::Errors(errors.iter().map(|e| e.to_string()).collect()),
+            },
+            "env" => Reply::Output(self.ctx.bindings()
+                .iter()
+                .map(|(name, t)| format!("{name} : {t}\n"))
+                .collect()),
+            "reset" => {
+                self.ctx = TypeContext::new();
+                self.env = Env::new();
+                Reply::Output(String::new())
+            }
+            "load" | "l" if !arg.is_empty() => self.load(Path::new(arg)),
+            "history" => Reply::Output(self.history.iter()
+                .enumerate()
+                .map(|(i, input)| format!("{:>4}  {}\n", i + 1, input.replace('\n', "\n      ")))
+                .collect()),
+            "help" | "h" | "?" => Reply::Output(format!("{HELP}\n")),
+            "quit" | "q" => Reply::Quit,
+            "load" | "l" => Reply::Errors(vec!["Usage: :load <file>".to_string()]),
+            _ => Reply::Errors(vec![format!("Unknown command :{command}, see :help.")]),
+        }
+    }
+
+    fn infer(&mut self, e: &Expression) -> Result<PolyType, Reply> {
+        let t = typck::infer_in(&mut self.ctx, e);
+        match self.ctx.take_errors() {
+            errors if errors.is_empty() => Ok(t),
+            errors => Err(Reply::Errors(errors.iter().map(|e| e.to_string()).collect())),
+        }
+    }
+
+    fn load(&mut self, path: &Path) -> Reply {
+        let src = match std::fs::read_to_string(path) {
+            Ok(src) => src,
+            Err(err) => return Reply::Errors(vec![format!("Couldn't read {}: {err}", path.display())]),
+        };
+        let (program, errors) = ast::parse_program_tolerant(&src);
+        if !errors.is_empty() {
+            return Reply::Errors(errors.iter().map(|e| e.to_string()).collect());
+        }
+
+        // the file can import the modules next to it as well as the session's
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+        self.loader.add_path(dir);
+        declare(&mut self.ctx, &mut self.env, &mut self.loader, &program)
+    }
+}
+
+/* Binds the declarations of 'program' in 'ctx' and 'env' and replies with
+ * their types, or leaves both as they were if any of them has an error. */
+fn declare(ctx: &mut TypeContext, env: &mut Env, loader: &mut ModuleLoader, program: &Program) -> Reply {
+    let scope = ctx.scope();
+    let sig = typck::infer_program_in(ctx, loader, program);
+    let errors = ctx.take_errors();
+    if !errors.is_empty() {
+        ctx.discard_scope(scope);
+        return Reply::Errors(errors.iter().map(|e| e.to_string()).collect());
+    }
+
+    // well-typed declarations don't get stuck, and imported modules are
+    // evaluated as the loader read and checked them
+    match eval::eval_program_in(loader, env, program) {
+        Ok((bound, _)) => {
+            *env = bound;
+            Reply::Output(sig.to_string())
+        }
+        Err(err) => {
+            ctx.discard_scope(scope);
+            Reply::Errors(vec![err.to_string()])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::module::tests::write_modules;
+
+    fn output(s: &str) -> Reply {
+        Reply::Output(s.to_string())
+    }
+
+    #[test]
+    fn test_session() {
+        let mut session = Session::default();
+        assert_eq!(session.handle(r"let id = \x. x"), output("id : forall a. a -> a\n"));
+        assert_eq!(session.handle("id true"), output("Bool\n"));
+        assert_eq!(session.handle(":t id id"), output("forall a. a -> a\n"));
+        assert_eq!(session.handle(r":eval id (\x. x) false"), output("false : Bool\n"));
+        assert_eq!(session.handle(r"let k = \x. \y. x in k id"), output("forall a b. a -> b -> b\n"));
+
+        // nothing gets bound when a declaration doesn't type check
+        assert!(matches!(session.handle("let a = id true\nlet b = true true"), Reply::Errors(_)));
+        assert_eq!(session.handle(":env"), output("id : forall a. a -> a\n"));
+
+        assert_eq!(session.handle("let id = true"), output("id : Bool\n"));
+        assert_eq!(session.handle(":env"), output("id : Bool\n"));
+        assert_eq!(session.handle(":reset"), output(""));
+        assert_eq!(session.handle("id"), Reply::Errors(vec!["Variable id not found.".to_string()]));
+        assert_eq!(session.handle(":quit"), Reply::Quit);
+    }
+
+    #[test]
+    fn test_load() {
+        let root = write_modules("repl-load", &[
+            ("lib/K.lam", "let k = \\x. \\y. x"),
+            ("lib/main.lam", "import K\nlet t = K.k true false"),
+            ("other/use.lam", "import K\nlet u = K.k false"),
+        ]);
+        let mut session = Session::new(ModuleLoader::default());
+        let main = root.join("lib/main.lam");
+        assert_eq!(session.handle(&format!(":load {}", main.display())), output("t : Bool\n"));
+        assert_eq!(session.handle("K.k"), output("forall a b. a -> b -> a\n"));
+        assert_eq!(session.handle(":eval t"), output("true : Bool\n"));
+
+        // the session's loader keeps K, so files elsewhere can import it too
+        let other = root.join("other/use.lam");
+        assert_eq!(session.handle(&format!(":load {}", other.display())), output("u : forall a. a -> Bool\n"));
+
+        assert_eq!(session.handle(":l"), Reply::Errors(vec!["Usage: :load <file>".to_string()]));
+        let missing = root.join("missing.lam");
+        assert!(matches!(session.handle(&format!(":load {}", missing.display())), Reply::Errors(_)));
+    }
+
+    #[test]
+    fn test_commands() {
+        let mut session = Session::default();
+        session.handle("let a = true");
+        session.handle("module M = struct\n  let b = a\nend");
+        assert_eq!(session.handle(":history"), output("   1  let a = true\n   2  module M = struct\n        let b = a\n      end\n   3  :history\n"));
+        assert_eq!(session.handle(":h"), output(&format!("{HELP}\n")));
+        assert!(HELP.lines().any(|l| l.starts_with(":history")));
+        assert_eq!(session.handle(":frobnicate"), Reply::Errors(vec!["Unknown command :frobnicate, see :help.".to_string()]));
+    }
+
+    #[test]
+    fn test_incomplete() {
+        assert!(is_incomplete(r"let f = \x."));
+        assert!(is_incomplete(r"let x = true in"));
+        assert!(is_incomplete("module M = struct\n  let x = true"));
+        assert!(!is_incomplete("module M = struct\n  let x = true\nend"));
+        assert!(!is_incomplete("let x = true"));
+        assert!(!is_incomplete("true )"));
+        assert!(!is_incomplete(":type"));
+    }
+}
@@ -0,0 +1,229 @@
+/*  Infers a list of top-level definitions in parallel.
+ *
+ *  Each definition can use the ones before it, so the definitions form a
+ *  dependency graph where a definition depends on the latest earlier definition
+ *  of every name it uses. Since let isn't recursive, every strongly connected
+ *  component of that graph is a single definition, so each definition gets
+ *  inferred on its own as soon as everything it depends on has been inferred.
+ *
+ *  Every definition is inferred in a TypeContext of its own, which only holds
+ *  the polytypes of its dependencies. The other names it could have used are
+ *  only known to it for suggesting them in place of names that aren't found,
+ *  which doesn't need their types. The results don't depend on the order the
+ *  workers happen to run in and are handed back in the order of definition. */
+
+use crate::tast::TypedExpression;
+use crate::typ::PolyType;
+use crate::typck::{self, TypeError};
+use crate::TypeContext;
+
+use ast::Expression;
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+/// The result of inferring a single top-level definition.
+#[derive(Debug)]
+pub struct Inferred {
+    pub name: Arc<str>,
+    pub typ: PolyType,
+    pub typed: TypedExpression,
+    /// Every error along with the post-order index of the node it was found
+    /// at, counting from the first node of the definition.
+    pub errors: Vec<(TypeError, Option<usize>)>,
+}
+
+struct State {
+    ready: VecDeque<usize>,
+    // number of dependencies that haven't been inferred yet
+    waiting: Vec<usize>,
+    results: Vec<Option<Inferred>>,
+    done: usize,
+}
+
+/// For each definition, the earlier definitions that it uses.
+pub fn dependencies(defs: &[(Arc<str>, Expression)]) -> Vec<Vec<usize>> {
+    defs.iter()
+        .enumerate()
+        .map(|(i, (_, e))| {
+            let mut deps = e.free_vars()
+                .iter()
+                .filter_map(|name| defs[..i].iter().rposition(|(n, _)| n == name))
+                .collect::<Vec<_>>();
+            deps.sort();
+            deps
+        })
+        .collect()
+}
+
+/// Infers every definition in 'defs' on up to 'threads' threads. Definitions
+/// can use the ones before them as well as the polytypes in 'env'.
+pub fn infer_parallel(
+    env: &[(Arc<str>, PolyType)],
+    defs: &[(Arc<str>, Expression)],
+    threads: usize,
+) -> Vec<Inferred> {
+    let deps = dependencies(defs);
+    let mut dependents = vec![vec![]; defs.len()];
+    for (i, ds) in deps.iter().enumerate() {
+        for j in ds {
+            dependents[*j].push(i);
+        }
+    }
+
+    let waiting = deps.iter().map(|ds| ds.len()).collect::<Vec<_>>();
+    let ready = (0..defs.len()).filter(|i| waiting[*i] == 0).collect();
+    let results = defs.iter().map(|_| None).collect();
+
+    let state = Mutex::new(State { ready, waiting, results, done: 0 });
+    let cvar = Condvar::new();
+
+    thread::scope(|s| {
+        for _ in 0..threads.clamp(1, defs.len().max(1)) {
+            s.spawn(|| worker(env, defs, &deps, &dependents, &state, &cvar));
+        }
+    });
+
+    state.into_inner()
+        .unwrap()
+        .results
+        .into_iter()
+        .map(|r| r.expect("Every definition is inferred once the workers are done"))
+        .collect()
+}
+
+/// Infers a single definition in a TypeContext of its own, which only holds
+/// the polytypes in 'scope'. Names in 'known' can be suggested for names that
+/// aren't found, see TypeContext::insert_known.
+pub fn infer_definition<'a>(
+    scope: impl IntoIterator<Item = &'a (Arc<str>, PolyType)>,
+    known: impl IntoIterator<Item = &'a Arc<str>>,
+    name: &Arc<str>,
+    e: &Expression,
+) -> Inferred {
+    let mut ctx = TypeContext::new();
+    for sym in known {
+        ctx.insert_known(sym.clone());
+    }
+    for (sym, t) in scope {
+        ctx.insert_poly(sym.clone(), t);
+    }
+    let (typ, typed) = typck::infer_typed_in(&mut ctx, e);
+    Inferred { name: name.clone(), typ, typed, errors: ctx.take_located_errors() }
+}
+
+fn worker(
+    env: &[(Arc<str>, PolyType)],
+    defs: &[(Arc<str>, Expression)],
+    deps: &[Vec<usize>],
+    dependents: &[Vec<usize>],
+    state: &Mutex<State>,
+    cvar: &Condvar,
+) {
+    loop {
+        let mut st = state.lock().unwrap();
+        let i = loop {
+            if st.done == defs.len() { return; }
+            match st.ready.pop_front() {
+                Some(i) => break i,
+                None => st = cvar.wait(st).unwrap(),
+            }
+        };
+
+        let dep_types = deps[i].iter()
+            .map(|j| {
+                let r = st.results[*j].as_ref().expect("Dependencies are inferred first");
+                (r.name.clone(), r.typ.clone())
+            })
+            .collect::<Vec<_>>();
+        drop(st);
+
+        let (name, e) = &defs[i];
+        let known = env.iter().map(|(n, _)| n).chain(defs[..i].iter().map(|(n, _)| n));
+        let inferred = infer_definition(env.iter().chain(&dep_types), known, name, e);
+
+        let mut st = state.lock().unwrap();
+        st.results[i] = Some(inferred);
+        st.done += 1;
+        for k in &dependents[i] {
+            st.waiting[*k] -= 1;
+            if st.waiting[*k] == 0 {
+                st.ready.push_back(*k);
+            }
+        }
+        cvar.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typ::parse_type;
+
+    fn defs(src: &[(&str, &str)]) -> Vec<(Arc<str>, Expression)> {
+        src.iter()
+            .map(|(name, e)| ((*name).into(), ast::parse(e).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_dependencies() {
+        let defs = defs(&[
+            ("id", r"\x. x"),
+            ("k", r"\x. \y. x"),
+            ("a", r"id k"),
+            ("id", r"\y. id y"),
+            ("b", r"\id. id a"),
+        ]);
+        assert_eq!(dependencies(&defs), vec![vec![], vec![], vec![0, 1], vec![0], vec![2]]);
+    }
+
+    #[test]
+    fn test_infer_parallel() {
+        let defs = defs(&[
+            ("id", r"\x. x"),
+            ("k", r"\x. \y. x"),
+            ("compose", r"\f. \g. \x. f (g x)"),
+            ("a", r"k id true"),
+            ("b", r"compose id id"),
+            ("c", r"compose k id"),
+            ("bad", r"id id id (\x. x x)"),
+            ("d", r"bad true"),
+        ]);
+        let expected = [
+            "forall a. a -> a",
+            "forall a b. a -> b -> a",
+            "forall a b c. (a -> b) -> (c -> a) -> c -> b",
+            "forall a. a -> a",
+            "forall a. a -> a",
+            "forall a b. a -> b -> a",
+            "forall a. a -> <error>",
+            "<error>",
+        ];
+
+        for threads in [1, 2, 8] {
+            let inferred = infer_parallel(&[], &defs, threads);
+            for ((r, (name, _)), t) in inferred.iter().zip(&defs).zip(expected) {
+                assert_eq!(&r.name, name);
+                assert!(r.typ.alpha_eq(&parse_type(t).unwrap()), "{name} : {} isn't {t}", r.typ);
+            }
+            assert_eq!(inferred[6].errors.len(), 1);
+            assert!(inferred[7].errors.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_similar_names() {
+        // only names defined before a definition are suggested, even though
+        // none of them are its dependencies
+        let defs = defs(&[
+            ("id", r"\x. x"),
+            ("a", r"idd true"),
+            ("idd", r"id"),
+        ]);
+        let inferred = infer_parallel(&[], &defs, 2);
+        let errors = inferred[1].errors.iter().map(|(err, _)| err.to_string()).collect::<Vec<_>>();
+        assert_eq!(errors, ["Variable idd not found. Did you mean id?"]);
+    }
+}
@@ -0,0 +1,689 @@
+/*  A stable serialization format for inference results, so that they can be
+ *  cached, diffed and handed to other tools.
+ *
+ *  Everything is described as JSON first, where every value is an object that
+ *  starts with a "kind", e.g.
+ *
+ *      forall a. a -> a
+ *      {"kind":"forall","vars":[0],"type":{"kind":"func","l":{"kind":"var","id":0},"r":{"kind":"var","id":0}}}
+ *
+ *  The S-expression form is the same data written as Lisp, see sexp.rs:
+ *
+ *      (forall :vars (0) :type (func :l (var :id 0) :r (var :id 0)))
+ *
+ *  MonoTypes are serialized as the Type they zonk to, and expressions inside
+ *  errors like typed expressions without their types. Values can only be
+ *  written out, since a closure's environment can't be read back without the
+ *  program it came from. */
+
+mod json;
+pub use json::Json;
+
+mod sexp;
+pub use sexp::Sexp;
+
+use crate::compile::CompileError;
+use crate::eval::{EvalError, Value};
+use crate::module::ModuleError;
+use crate::sig::SignatureParseError;
+use crate::tast::{TypedExpression, TypedKind};
+use crate::typ::{MonoType, PolyType, Type, TypeParseError};
+use crate::typck::TypeError;
+use crate::vm;
+
+use ast::{Expression, ParseError, TokenKind};
+use thiserror::Error;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/* How deeply arrays, objects and lists can be nested in the input before
+ * parsing gives up, so that it can't run out of stack. */
+const MAX_DEPTH: usize = 512;
+
+/// An error found while reading a serialized value back.
+#[derive(Debug, PartialEq, Error)]
+pub enum DecodeError {
+    #[error("Invalid JSON at position {pos}: {msg}.")]
+    Json { msg: &'static str, pos: usize },
+    #[error("Invalid S-expression at position {pos}: {msg}.")]
+    Sexp { msg: &'static str, pos: usize },
+    #[error("Expected {expected}, found {found}.")]
+    Shape { expected: &'static str, found: String },
+}
+
+/// Values that can be written out as JSON or as an S-expression.
+pub trait Serialize {
+    fn to_json(&self) -> Json;
+
+    fn to_sexp(&self) -> Sexp {
+        Sexp::from(&self.to_json())
+    }
+}
+
+/// Values that can be read back from what Serialize wrote.
+pub trait Deserialize: Sized {
+    fn from_json(json: &Json) -> Result<Self, DecodeError>;
+
+    fn from_sexp(sexp: &Sexp) -> Result<Self, DecodeError> {
+        Self::from_json(&Json::try_from(sexp)?)
+    }
+}
+
+/// Parses 'src' as JSON and decodes it.
+pub fn from_json_str<T: Deserialize>(src: &str) -> Result<T, DecodeError> {
+    T::from_json(&src.parse()?)
+}
+
+/// Parses 'src' as an S-expression and decodes it.
+pub fn from_sexp_str<T: Deserialize>(src: &str) -> Result<T, DecodeError> {
+    T::from_sexp(&src.parse()?)
+}
+
+/* Helpers for taking apart the objects written by Serialize */
+
+fn shape(expected: &'static str, found: &Json) -> DecodeError {
+    DecodeError::Shape { expected, found: found.to_string() }
+}
+
+fn kind(json: &Json) -> Result<&str, DecodeError> {
+    json.get("kind").and_then(Json::as_str).ok_or_else(|| shape("an object with a kind", json))
+}
+
+fn field<'j>(json: &'j Json, key: &'static str) -> Result<&'j Json, DecodeError> {
+    json.get(key).ok_or_else(|| DecodeError::Shape { expected: key, found: json.to_string() })
+}
+
+fn str_field(json: &Json, key: &'static str) -> Result<Arc<str>, DecodeError> {
+    let value = field(json, key)?;
+    value.as_str().map(Into::into).ok_or_else(|| shape("a string", value))
+}
+
+fn usize_field(json: &Json, key: &'static str) -> Result<usize, DecodeError> {
+    let value = field(json, key)?;
+    value.as_u64().and_then(|n| n.try_into().ok()).ok_or_else(|| shape("an index", value))
+}
+
+fn bool_field(json: &Json, key: &'static str) -> Result<bool, DecodeError> {
+    let value = field(json, key)?;
+    value.as_bool().ok_or_else(|| shape("a boolean", value))
+}
+
+fn list_field<T>(
+    json: &Json,
+    key: &'static str,
+    item: impl Fn(&Json) -> Result<T, DecodeError>,
+) -> Result<Vec<T>, DecodeError> {
+    let value = field(json, key)?;
+    value.as_array()
+        .ok_or_else(|| shape("an array", value))?
+        .iter()
+        .map(item)
+        .collect()
+}
+
+fn decode_field<T: Deserialize>(json: &Json, key: &'static str) -> Result<T, DecodeError> {
+    T::from_json(field(json, key)?)
+}
+
+fn strs(items: &[impl AsRef<str>]) -> Json {
+    Json::Array(items.iter().map(|s| s.as_ref().into()).collect())
+}
+
+fn as_str(json: &Json) -> Result<Arc<str>, DecodeError> {
+    json.as_str().map(Into::into).ok_or_else(|| shape("a string", json))
+}
+
+fn tvar_id(json: &Json) -> Result<u32, DecodeError> {
+    json.as_u64().and_then(|id| id.try_into().ok()).ok_or_else(|| shape("a type variable id", json))
+}
+
+fn list<T: Serialize>(items: &[T]) -> Json {
+    Json::Array(items.iter().map(Serialize::to_json).collect())
+}
+
+impl<T: Serialize> Serialize for Arc<T> {
+    fn to_json(&self) -> Json {
+        (**self).to_json()
+    }
+}
+
+impl<T: Deserialize> Deserialize for Arc<T> {
+    fn from_json(json: &Json) -> Result<Arc<T>, DecodeError> {
+        T::from_json(json).map(Arc::new)
+    }
+}
+
+impl Serialize for Type {
+    fn to_json(&self) -> Json {
+        match self {
+            Type::Bool => Json::object([("kind", "bool".into())]),
+            Type::Func { l, r } => Json::object([("kind", "func".into()), ("l", l.to_json()), ("r", r.to_json())]),
+            Type::Var { id } => Json::object([("kind", "var".into()), ("id", u64::from(*id).into())]),
+            Type::Error => Json::object([("kind", "error".into())]),
+        }
+    }
+}
+
+impl Deserialize for Type {
+    fn from_json(json: &Json) -> Result<Type, DecodeError> {
+        let typ = match kind(json)? {
+            "bool" => Type::Bool,
+            "func" => Type::Func { l: decode_field(json, "l")?, r: decode_field(json, "r")? },
+            "var" => Type::Var { id: tvar_id(field(json, "id")?)? },
+            "error" => Type::Error,
+            _ => return Err(shape("a type", json)),
+        };
+        Ok(typ)
+    }
+}
+
+impl Serialize for MonoType {
+    fn to_json(&self) -> Json {
+        self.zonk().to_json()
+    }
+}
+
+impl Serialize for PolyType {
+    fn to_json(&self) -> Json {
+        let vars = self.tvar_ids.iter().map(|id| u64::from(*id)).collect::<Vec<_>>();
+        Json::object([("kind", "forall".into()), ("vars", vars.into()), ("type", self.typ.to_json())])
+    }
+}
+
+impl Deserialize for PolyType {
+    fn from_json(json: &Json) -> Result<PolyType, DecodeError> {
+        if kind(json)? != "forall" {
+            return Err(shape("a polytype", json));
+        }
+        let tvar_ids = list_field(json, "vars", tvar_id)?;
+        Ok(PolyType::new(tvar_ids, decode_field(json, "type")?))
+    }
+}
+
+impl Serialize for TypeError {
+    fn to_json(&self) -> Json {
+        match self {
+            TypeError::RecursiveType { var, typ, expr } => Json::object([
+                ("kind", "recursive_type".into()),
+                ("var", var.to_json()),
+                ("type", typ.to_json()),
+                ("expr", expr.to_json()),
+            ]),
+            TypeError::TypeMismatch(t0, t1) => Json::object([
+                ("kind", "type_mismatch".into()),
+                ("left", t0.to_json()),
+                ("right", t1.to_json()),
+            ]),
+            TypeError::VarNotFound { name, similar, out_of_scope } => Json::object([
+                ("kind", "var_not_found".into()),
+                ("name", (**name).into()),
+                ("similar", strs(similar)),
+                ("out_of_scope", (*out_of_scope).into()),
+            ]),
+            TypeError::Import(err) => Json::object([
+                ("kind", "import".into()),
+                ("error", err.to_json()),
+            ]),
+            TypeError::NotDefined { name } => Json::object([
+                ("kind", "not_defined".into()),
+                ("name", (**name).into()),
+            ]),
+            TypeError::SignatureMismatch { name, declared, inferred } => Json::object([
+                ("kind", "signature_mismatch".into()),
+                ("name", (**name).into()),
+                ("declared", declared.to_json()),
+                ("inferred", inferred.to_json()),
+            ]),
+        }
+    }
+}
+
+impl Deserialize for TypeError {
+    fn from_json(json: &Json) -> Result<TypeError, DecodeError> {
+        let err = match kind(json)? {
+            "recursive_type" => TypeError::RecursiveType {
+                var: decode_field(json, "var")?,
+                typ: decode_field(json, "type")?,
+                expr: decode_field(json, "expr")?,
+            },
+            "type_mismatch" => TypeError::TypeMismatch(decode_field(json, "left")?, decode_field(json, "right")?),
+            "var_not_found" => TypeError::VarNotFound {
+                name: str_field(json, "name")?,
+                similar: list_field(json, "similar", as_str)?,
+                out_of_scope: bool_field(json, "out_of_scope")?,
+            },
+            "import" => TypeError::Import(decode_field(json, "error")?),
+            "not_defined" => TypeError::NotDefined { name: str_field(json, "name")? },
+            "signature_mismatch" => TypeError::SignatureMismatch {
+                name: str_field(json, "name")?,
+                declared: decode_field(json, "declared")?,
+                inferred: decode_field(json, "inferred")?,
+            },
+            _ => return Err(shape("a type error", json)),
+        };
+        Ok(err)
+    }
+}
+
+/* An io::Error is written as its message and read back as one with
+ * ErrorKind::Other, which displays the same way. */
+impl Serialize for ModuleError {
+    fn to_json(&self) -> Json {
+        let path = |p: &PathBuf| Json::from(p.display().to_string().as_str());
+        match self {
+            ModuleError::NotFound { name, searched } => Json::object([
+                ("kind", "not_found".into()),
+                ("module", (**name).into()),
+                ("searched", Json::Array(searched.iter().map(path).collect())),
+            ]),
+            ModuleError::Io { name, path: p, err } => Json::object([
+                ("kind", "io".into()),
+                ("module", (**name).into()),
+                ("path", path(p)),
+                ("message", err.to_string().as_str().into()),
+            ]),
+            ModuleError::Cyclic { name, cycle } => Json::object([
+                ("kind", "cyclic".into()),
+                ("module", (**name).into()),
+                ("cycle", strs(cycle)),
+            ]),
+            ModuleError::Parse { name, errors } => Json::object([
+                ("kind", "parse".into()),
+                ("module", (**name).into()),
+                ("errors", list(errors)),
+            ]),
+            ModuleError::Signature { name, path: p, err } => Json::object([
+                ("kind", "signature".into()),
+                ("module", (**name).into()),
+                ("path", path(p)),
+                ("error", err.to_json()),
+            ]),
+            ModuleError::Type { name, errors } => Json::object([
+                ("kind", "type".into()),
+                ("module", (**name).into()),
+                ("errors", list(errors)),
+            ]),
+        }
+    }
+}
+
+impl Deserialize for ModuleError {
+    fn from_json(json: &Json) -> Result<ModuleError, DecodeError> {
+        let name = str_field(json, "module")?;
+        let path = |key| str_field(json, key).map(|p| PathBuf::from(&*p));
+        let err = match kind(json)? {
+            "not_found" => ModuleError::NotFound {
+                name,
+                searched: list_field(json, "searched", |p| as_str(p).map(|p| PathBuf::from(&*p)))?,
+            },
+            "io" => ModuleError::Io {
+                name,
+                path: path("path")?,
+                err: std::io::Error::other(str_field(json, "message")?.to_string()).into(),
+            },
+            "cyclic" => ModuleError::Cyclic { name, cycle: list_field(json, "cycle", as_str)? },
+            "parse" => ModuleError::Parse { name, errors: list_field(json, "errors", ParseError::from_json)? },
+            "signature" => ModuleError::Signature { name, path: path("path")?, err: decode_field(json, "error")? },
+            "type" => ModuleError::Type { name, errors: list_field(json, "errors", TypeError::from_json)? },
+            _ => return Err(shape("a module error", json)),
+        };
+        Ok(err)
+    }
+}
+
+impl Serialize for ParseError {
+    fn to_json(&self) -> Json {
+        match self {
+            ParseError::UnexpectedToken { unexpected, expected, pos } => Json::object([
+                ("kind", "unexpected_token".into()),
+                ("unexpected", unexpected.to_string().as_str().into()),
+                ("expected", strs(&expected.iter().map(|t| t.to_string()).collect::<Vec<_>>())),
+                ("pos", (*pos as u64).into()),
+            ]),
+            ParseError::InvalidToken { lexeme, pos } => Json::object([
+                ("kind", "invalid_token".into()),
+                ("lexeme", lexeme.as_str().into()),
+                ("pos", (*pos as u64).into()),
+            ]),
+        }
+    }
+}
+
+impl Deserialize for ParseError {
+    fn from_json(json: &Json) -> Result<ParseError, DecodeError> {
+        let token = |t: &Json| {
+            t.as_str().and_then(|t| t.parse::<TokenKind>().ok()).ok_or_else(|| shape("a token", t))
+        };
+        let err = match kind(json)? {
+            "unexpected_token" => ParseError::UnexpectedToken {
+                unexpected: token(field(json, "unexpected")?)?,
+                expected: list_field(json, "expected", token)?,
+                pos: usize_field(json, "pos")?,
+            },
+            "invalid_token" => ParseError::InvalidToken {
+                lexeme: str_field(json, "lexeme")?.to_string(),
+                pos: usize_field(json, "pos")?,
+            },
+            _ => return Err(shape("a parse error", json)),
+        };
+        Ok(err)
+    }
+}
+
+impl Serialize for SignatureParseError {
+    fn to_json(&self) -> Json {
+        match self {
+            SignatureParseError::Malformed { line } => Json::object([
+                ("kind", "malformed".into()),
+                ("line", (*line as u64).into()),
+            ]),
+            SignatureParseError::Type { line, err } => Json::object([
+                ("kind", "type".into()),
+                ("line", (*line as u64).into()),
+                ("error", err.to_json()),
+            ]),
+        }
+    }
+}
+
+impl Deserialize for SignatureParseError {
+    fn from_json(json: &Json) -> Result<SignatureParseError, DecodeError> {
+        let line = usize_field(json, "line")?;
+        let err = match kind(json)? {
+            "malformed" => SignatureParseError::Malformed { line },
+            "type" => SignatureParseError::Type { line, err: decode_field(json, "error")? },
+            _ => return Err(shape("a signature error", json)),
+        };
+        Ok(err)
+    }
+}
+
+impl Serialize for TypeParseError {
+    fn to_json(&self) -> Json {
+        match self {
+            TypeParseError::UnexpectedToken { found, expected, pos } => Json::object([
+                ("kind", "unexpected_token".into()),
+                ("found", found.as_str().into()),
+                ("expected", (*expected).into()),
+                ("pos", (*pos as u64).into()),
+            ]),
+            TypeParseError::UnquantifiedVar { name, pos } => Json::object([
+                ("kind", "unquantified_var".into()),
+                ("name", name.as_str().into()),
+                ("pos", (*pos as u64).into()),
+            ]),
+            TypeParseError::DuplicateVar { name, pos } => Json::object([
+                ("kind", "duplicate_var".into()),
+                ("name", name.as_str().into()),
+                ("pos", (*pos as u64).into()),
+            ]),
+        }
+    }
+}
+
+impl Deserialize for TypeParseError {
+    fn from_json(json: &Json) -> Result<TypeParseError, DecodeError> {
+        let err = match kind(json)? {
+            "unexpected_token" => {
+                let expected = field(json, "expected")?;
+                TypeParseError::UnexpectedToken {
+                    found: str_field(json, "found")?.to_string(),
+                    expected: TypeParseError::EXPECTED.iter()
+                        .find(|e| Some(**e) == expected.as_str())
+                        .ok_or_else(|| shape("something the type parser expects", expected))?,
+                    pos: usize_field(json, "pos")?,
+                }
+            }
+            "unquantified_var" => TypeParseError::UnquantifiedVar {
+                name: str_field(json, "name")?.to_string(),
+                pos: usize_field(json, "pos")?,
+            },
+            "duplicate_var" => TypeParseError::DuplicateVar {
+                name: str_field(json, "name")?.to_string(),
+                pos: usize_field(json, "pos")?,
+            },
+            _ => return Err(shape("a type parse error", json)),
+        };
+        Ok(err)
+    }
+}
+
+impl Serialize for Expression {
+    fn to_json(&self) -> Json {
+        match self {
+            Expression::Var { name } => Json::object([("kind", "var".into()), ("name", (**name).into())]),
+            Expression::App { f, e } => Json::object([
+                ("kind", "app".into()),
+                ("f", f.to_json()),
+                ("e", e.to_json()),
+            ]),
+            Expression::Abs { name, e } => Json::object([
+                ("kind", "abs".into()),
+                ("name", (**name).into()),
+                ("e", e.to_json()),
+            ]),
+            Expression::Let { name, e0, e1 } => Json::object([
+                ("kind", "let".into()),
+                ("name", (**name).into()),
+                ("e0", e0.to_json()),
+                ("e1", e1.to_json()),
+            ]),
+            Expression::True  => Json::object([("kind", "true".into())]),
+            Expression::False => Json::object([("kind", "false".into())]),
+            Expression::Error => Json::object([("kind", "error".into())]),
+        }
+    }
+}
+
+impl Deserialize for Expression {
+    fn from_json(json: &Json) -> Result<Expression, DecodeError> {
+        let sub = |key| decode_field::<Expression>(json, key).map(Box::new);
+        let e = match kind(json)? {
+            "var" => Expression::Var { name: str_field(json, "name")? },
+            "app" => Expression::App { f: sub("f")?, e: sub("e")? },
+            "abs" => Expression::Abs { name: str_field(json, "name")?, e: sub("e")? },
+            "let" => Expression::Let { name: str_field(json, "name")?, e0: sub("e0")?, e1: sub("e1")? },
+            "true"  => Expression::True,
+            "false" => Expression::False,
+            "error" => Expression::Error,
+            _ => return Err(shape("an expression", json)),
+        };
+        Ok(e)
+    }
+}
+
+impl Serialize for TypedExpression {
+    fn to_json(&self) -> Json {
+        let typ = ("type", self.typ.to_json());
+        match &self.kind {
+            TypedKind::Var { name } => Json::object([("kind", "var".into()), typ, ("name", (**name).into())]),
+            TypedKind::App { f, e } => Json::object([
+                ("kind", "app".into()),
+                typ,
+                ("f", f.to_json()),
+                ("e", e.to_json()),
+            ]),
+            TypedKind::Abs { name, e } => Json::object([
+                ("kind", "abs".into()),
+                typ,
+                ("name", (**name).into()),
+                ("e", e.to_json()),
+            ]),
+            TypedKind::Let { name, poly, e0, e1 } => Json::object([
+                ("kind", "let".into()),
+                typ,
+                ("name", (**name).into()),
+                ("poly", poly.to_json()),
+                ("e0", e0.to_json()),
+                ("e1", e1.to_json()),
+            ]),
+            TypedKind::True  => Json::object([("kind", "true".into()), typ]),
+            TypedKind::False => Json::object([("kind", "false".into()), typ]),
+            TypedKind::Error => Json::object([("kind", "error".into()), typ]),
+        }
+    }
+}
+
+impl Deserialize for TypedExpression {
+    fn from_json(json: &Json) -> Result<TypedExpression, DecodeError> {
+        let sub = |key| decode_field::<TypedExpression>(json, key).map(Box::new);
+        let kind = match kind(json)? {
+            "var" => TypedKind::Var { name: str_field(json, "name")? },
+            "app" => TypedKind::App { f: sub("f")?, e: sub("e")? },
+            "abs" => TypedKind::Abs { name: str_field(json, "name")?, e: sub("e")? },
+            "let" => TypedKind::Let {
+                name: str_field(json, "name")?,
+                poly: decode_field(json, "poly")?,
+                e0: sub("e0")?,
+                e1: sub("e1")?,
+            },
+            "true"  => TypedKind::True,
+            "false" => TypedKind::False,
+            "error" => TypedKind::Error,
+            _ => return Err(shape("a typed expression", json)),
+        };
+        Ok(TypedExpression::new(kind, decode_field(json, "type")?))
+    }
+}
+
+impl Serialize for Value {
+    fn to_json(&self) -> Json {
+        match self {
+            Value::Bool(b) => Json::object([("kind", "bool".into()), ("value", (*b).into())]),
+            Value::Closure(_) => Json::object([("kind", "function".into())]),
+        }
+    }
+}
+
+impl Serialize for vm::Value {
+    fn to_json(&self) -> Json {
+        match self {
+            vm::Value::Bool(b) => Json::object([("kind", "bool".into()), ("value", (*b).into())]),
+            vm::Value::Function => Json::object([("kind", "function".into())]),
+        }
+    }
+}
+
+impl Serialize for CompileError {
+    fn to_json(&self) -> Json {
+        match self {
+            CompileError::Type(errors) => Json::object([
+                ("kind", "type".into()),
+                ("errors", Json::Array(errors.iter().map(TypeError::to_json).collect())),
+            ]),
+            CompileError::IllTyped => Json::object([("kind", "ill_typed".into())]),
+            CompileError::Unbound { name } => Json::object([
+                ("kind", "unbound".into()),
+                ("name", (**name).into()),
+            ]),
+            CompileError::Import(err) => Json::object([
+                ("kind", "import".into()),
+                ("error", err.to_json()),
+            ]),
+        }
+    }
+}
+
+impl Serialize for EvalError {
+    fn to_json(&self) -> Json {
+        match self {
+            EvalError::Unbound { name } => Json::object([
+                ("kind", "unbound".into()),
+                ("name", (**name).into()),
+            ]),
+            EvalError::NotAFunction { value } => Json::object([
+                ("kind", "not_a_function".into()),
+                ("value", value.to_json()),
+            ]),
+            EvalError::Invalid => Json::object([("kind", "invalid".into())]),
+            EvalError::Import(err) => Json::object([
+                ("kind", "import".into()),
+                ("error", err.to_json()),
+            ]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typck;
+    use crate::typ::parse_type;
+
+    #[test]
+    fn test_formats() {
+        let t = parse_type("forall a. a -> a").unwrap();
+        assert_eq!(
+            t.to_json().to_string(),
+            r#"{"kind":"forall","vars":[0],"type":{"kind":"func","l":{"kind":"var","id":0},"r":{"kind":"var","id":0}}}"#,
+        );
+        assert_eq!(t.to_sexp().to_string(), "(forall :vars (0) :type (func :l (var :id 0) :r (var :id 0)))");
+
+        let json = r#" { "a" : [1.5, -2, "x\"é😀\n", true, null, {}], "kind": "k" } "#.parse::<Json>().unwrap();
+        let sexp = Sexp::from(&json);
+        assert_eq!(sexp.to_string(), r#"(:a (1.5 -2 "x\"é😀\n" #t #nil #{}) :kind "k")"#);
+        assert_eq!(Json::try_from(&sexp.to_string().parse::<Sexp>().unwrap()), Ok(json));
+        assert_eq!("[1,".parse::<Json>(), Err(DecodeError::Json { msg: "expected a value", pos: 3 }));
+        assert_eq!("(a".parse::<Sexp>(), Err(DecodeError::Sexp { msg: "unclosed '('", pos: 2 }));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let (e, _) = ast::parse_tolerant(r"let f = \x. x x in (\y. y) f zz ()");
+        let (typed, errors) = typck::infer_typed(e);
+        assert_eq!(errors.len(), 2);
+
+        for err in &errors {
+            let json: TypeError = from_json_str(&err.to_json().to_string()).unwrap();
+            let sexp: TypeError = from_sexp_str(&err.to_sexp().to_string()).unwrap();
+            assert_eq!(json.to_json(), err.to_json());
+            assert_eq!(sexp.to_string(), err.to_string());
+        }
+
+        let json = typed.to_json();
+        let decoded: TypedExpression = from_sexp_str(&typed.to_sexp().to_string()).unwrap();
+        assert_eq!(decoded.to_json(), json);
+
+        let poly: PolyType = from_json_str(&json.get("poly").unwrap().to_string()).unwrap();
+        assert_eq!(Some(&poly), match &typed.kind { TypedKind::Let { poly, .. } => Some(poly), _ => None });
+
+        let err = TypeError::Import(ModuleError::Parse { name: "M".into(), errors: vec![ast::parse("(").unwrap_err()] });
+        assert_eq!(from_json_str::<TypeError>(&err.to_json().to_string()).unwrap().to_string(), err.to_string());
+    }
+
+    #[test]
+    fn test_expression_with_errors() {
+        let (e, _) = ast::parse_tolerant(r"\x. x (\y. let z = () in x)");
+        let (_, errors) = typck::infer_typed(e);
+        let [err @ TypeError::RecursiveType { expr, .. }] = &errors[..] else { panic!("{errors:?}") };
+        assert!(expr.to_string().contains("<error>"), "{expr}");
+
+        let decoded: TypeError = from_json_str(&err.to_json().to_string()).unwrap();
+        assert_eq!(decoded.to_string(), err.to_string());
+        let decoded: TypeError = from_sexp_str(&err.to_sexp().to_string()).unwrap();
+        assert_eq!(decoded.to_string(), err.to_string());
+    }
+
+    #[test]
+    fn test_limits() {
+        let deep = "[".repeat(MAX_DEPTH + 1) + &"]".repeat(MAX_DEPTH + 1);
+        assert!(matches!(deep.parse::<Json>(), Err(DecodeError::Json { msg: "nested too deeply", pos: MAX_DEPTH })));
+        let ok = "[".repeat(MAX_DEPTH) + &"]".repeat(MAX_DEPTH);
+        assert!(ok.parse::<Json>().is_ok());
+        let deep = "(".repeat(MAX_DEPTH + 1) + &")".repeat(MAX_DEPTH + 1);
+        assert!(matches!(deep.parse::<Sexp>(), Err(DecodeError::Sexp { msg: "nested too deeply", pos: MAX_DEPTH })));
+
+        assert_eq!(r#""\u00e9""#.parse::<Json>(), Ok(Json::String("é".into())));
+        for escape in [r#""\u+0e9""#, r#""\u-0e9""#, r#""\u00g9""#, r#""\u00e""#] {
+            assert_eq!(escape.parse::<Json>(), Err(DecodeError::Json { msg: "invalid unicode escape", pos: 3 }), "{escape}");
+        }
+
+        for raw in ["\"a\nb\"", "\"a\tb\"", "\"a\u{0}b\""] {
+            assert_eq!(raw.parse::<Json>(), Err(DecodeError::Json { msg: "control character in string", pos: 2 }), "{raw:?}");
+        }
+        assert_eq!(r#""a\nb\u0000""#.parse::<Json>(), Ok(Json::String("a\nb\u{0}".into())));
+        assert_eq!("\"a\u{7f}\"".parse::<Json>(), Ok(Json::String("a\u{7f}".into())));
+    }
+
+}
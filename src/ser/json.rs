@@ -0,0 +1,318 @@
+/*  A small JSON value with a printer and a parser, enough to exchange results
+ *  with other tools without pulling in a dependency.
+ *
+ *  Objects keep their keys in order, so printing the same value always gives
+ *  the same text. */
+
+use super::{DecodeError, MAX_DEPTH};
+
+use std::fmt::Write;
+
+/// A JSON value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Builds an object out of (key, value) pairs.
+    pub fn object<'k>(fields: impl IntoIterator<Item = (&'k str, Json)>) -> Json {
+        Json::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+
+    /// The value of 'key', if this is an object that has it.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// The number as an integer, if it is a non-negative integer.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Json::Number(n) if *n >= 0.0 && n.fract() == 0.0 && *n <= MAX_SAFE_INT => Some(*n as u64),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+// integers up to 2^53 survive the round trip through f64
+const MAX_SAFE_INT: f64 = 9007199254740992.0;
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Json {
+        Json::String(s.to_string())
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Json {
+        Json::Bool(b)
+    }
+}
+
+impl From<u64> for Json {
+    fn from(n: u64) -> Json {
+        Json::Number(n as f64)
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(items: Vec<T>) -> Json {
+        Json::Array(items.into_iter().map(Into::into).collect())
+    }
+}
+
+/// Prints the value on a single line.
+impl std::fmt::Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{b}"),
+            // JSON has no NaN or infinity
+            Json::Number(n) if !n.is_finite() => write!(f, "null"),
+            Json::Number(n) if n.fract() == 0.0 && n.abs() <= MAX_SAFE_INT => write!(f, "{}", *n as i64),
+            Json::Number(n) => write!(f, "{n}"),
+            Json::String(s) => write_string(f, s),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 { write!(f, ",")?; }
+                    write!(f, "{item}")?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (k, v)) in fields.iter().enumerate() {
+                    if i > 0 { write!(f, ",")?; }
+                    write_string(f, k)?;
+                    write!(f, ":{v}")?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// Writes 's' as a quoted string with JSON escapes, which the S-expression
+/// form uses too.
+pub(super) fn write_string(f: &mut impl Write, s: &str) -> std::fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"'  => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+impl std::str::FromStr for Json {
+    type Err = DecodeError;
+
+    fn from_str(s: &str) -> Result<Json, DecodeError> {
+        let mut p = Parser { src: s, pos: 0, depth: 0 };
+        let json = p.value()?;
+        p.skip_whitespace();
+        match p.pos == s.len() {
+            true => Ok(json),
+            false => Err(p.error("trailing characters")),
+        }
+    }
+}
+
+struct Parser<'src> {
+    src: &'src str,
+    // a byte offset into 'src'
+    pos: usize,
+    // the number of arrays and objects the parser is inside of
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, msg: &'static str) -> DecodeError {
+        DecodeError::Json { msg, pos: self.pos }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() && matches!(c, ' ' | '\t' | '\n' | '\r') {
+            self.bump();
+        }
+    }
+
+    fn eat(&mut self, word: &str) -> bool {
+        let matches = self.src[self.pos..].starts_with(word);
+        if matches { self.pos += word.len(); }
+        matches
+    }
+
+    fn value(&mut self) -> Result<Json, DecodeError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{' | '[') => {
+                if self.depth == MAX_DEPTH { return Err(self.error("nested too deeply")); }
+                self.depth += 1;
+                let json = self.nested();
+                self.depth -= 1;
+                json
+            }
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            _ if self.eat("null")  => Ok(Json::Null),
+            _ if self.eat("true")  => Ok(Json::Bool(true)),
+            _ if self.eat("false") => Ok(Json::Bool(false)),
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    /* An object or an array */
+    fn nested(&mut self) -> Result<Json, DecodeError> {
+        match self.peek() {
+            Some('{') => {
+                self.bump();
+                let mut fields = vec![];
+                self.skip_whitespace();
+                if self.eat("}") { return Ok(Json::Object(fields)); }
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() != Some('"') { return Err(self.error("expected a key")); }
+                    let key = self.string()?;
+                    self.skip_whitespace();
+                    if !self.eat(":") { return Err(self.error("expected ':'")); }
+                    fields.push((key, self.value()?));
+                    self.skip_whitespace();
+                    if self.eat("}") { return Ok(Json::Object(fields)); }
+                    if !self.eat(",") { return Err(self.error("expected ',' or '}'")); }
+                }
+            }
+            Some('[') => {
+                self.bump();
+                let mut items = vec![];
+                self.skip_whitespace();
+                if self.eat("]") { return Ok(Json::Array(items)); }
+                loop {
+                    items.push(self.value()?);
+                    self.skip_whitespace();
+                    if self.eat("]") { return Ok(Json::Array(items)); }
+                    if !self.eat(",") { return Err(self.error("expected ',' or ']'")); }
+                }
+            }
+            _ => Err(self.error("expected '{' or '['")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, DecodeError> {
+        let start = self.pos;
+        while let Some(c) = self.peek() && (c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')) {
+            self.bump();
+        }
+        self.src[start..self.pos]
+            .parse()
+            .map(Json::Number)
+            .map_err(|_| DecodeError::Json { msg: "invalid number", pos: start })
+    }
+
+    // Parses a string, starting at its opening quote
+    fn string(&mut self) -> Result<String, DecodeError> {
+        self.bump();
+        let mut s = String::new();
+        loop {
+            match self.bump() {
+                None => return Err(self.error("unterminated string")),
+                Some('"') => return Ok(s),
+                Some('\\') => match self.bump() {
+                    Some('"')  => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('/')  => s.push('/'),
+                    Some('b')  => s.push('\u{8}'),
+                    Some('f')  => s.push('\u{c}'),
+                    Some('n')  => s.push('\n'),
+                    Some('r')  => s.push('\r'),
+                    Some('t')  => s.push('\t'),
+                    Some('u')  => s.push(self.unicode_escape()?),
+                    _ => return Err(self.error("invalid escape")),
+                },
+                // these have to be escaped, the character itself is one byte
+                Some(c) if c < '\u{20}' => {
+                    return Err(DecodeError::Json { msg: "control character in string", pos: self.pos - 1 });
+                }
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    // Characters outside the BMP are escaped as a pair of UTF-16 surrogates
+    fn unicode_escape(&mut self) -> Result<char, DecodeError> {
+        let hi = self.hex4()?;
+        let code = match hi {
+            0xd800..=0xdbff => {
+                if !self.eat("\\u") { return Err(self.error("unpaired surrogate")); }
+                let lo = self.hex4()?;
+                if !(0xdc00..=0xdfff).contains(&lo) { return Err(self.error("unpaired surrogate")); }
+                0x10000 + ((hi - 0xd800) << 10) + (lo - 0xdc00)
+            }
+            code => code,
+        };
+        char::from_u32(code).ok_or(self.error("invalid unicode escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, DecodeError> {
+        // from_str_radix alone would also take a sign, as in \u+123
+        let hex = self.src.get(self.pos..self.pos + 4)
+            .filter(|hex| hex.chars().all(|c| c.is_ascii_hexdigit()))
+            .ok_or(self.error("invalid unicode escape"))?;
+        let code = u32::from_str_radix(hex, 16).map_err(|_| self.error("invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+}
+
+/// Parses a JSON string literal, which the S-expression parser shares.
+pub(super) fn parse_string(src: &str, pos: usize) -> Result<(String, usize), DecodeError> {
+    let mut p = Parser { src, pos, depth: 0 };
+    let s = p.string()?;
+    Ok((s, p.pos))
+}
@@ -0,0 +1,168 @@
+/*  The S-expression form of a JSON value. Objects are written as a tag
+ *  followed by keyword arguments, which reads a lot like the data it
+ *  describes:
+ *
+ *      {"kind":"func","l":{"kind":"bool"},"r":[1,"x",true,null]}
+ *      (func :l (bool) :r (1 "x" #t #nil))
+ *
+ *  The tag is the object's "kind", which every object serialized by this crate
+ *  starts with. An object without one is written with only keyword arguments.
+ *  Strings are always quoted and the other atoms start with '#' or are
+ *  numbers, so a bare symbol at the head of a list is always a tag. */
+
+use super::{DecodeError, Json, MAX_DEPTH};
+use super::json::{parse_string, write_string};
+
+/// An S-expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sexp {
+    // a symbol, keyword, number or one of #t, #f and #nil
+    Atom(String),
+    String(String),
+    List(Vec<Sexp>),
+}
+
+fn is_symbol(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic())
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn is_atom_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, '(' | ')' | '"')
+}
+
+impl From<&Json> for Sexp {
+    fn from(json: &Json) -> Sexp {
+        match json {
+            Json::Null => Sexp::Atom("#nil".into()),
+            Json::Bool(true) => Sexp::Atom("#t".into()),
+            Json::Bool(false) => Sexp::Atom("#f".into()),
+            Json::Number(_) => Sexp::Atom(json.to_string()),
+            Json::String(s) => Sexp::String(s.clone()),
+            Json::Array(items) => Sexp::List(items.iter().map(Sexp::from).collect()),
+            Json::Object(fields) if fields.is_empty() => Sexp::Atom("#{}".into()),
+            Json::Object(fields) => {
+                let (tag, fields) = match &fields[..] {
+                    [(k, Json::String(tag)), rest @ ..] if k == "kind" && is_symbol(tag) => {
+                        (Some(Sexp::Atom(tag.clone())), rest)
+                    }
+                    fields => (None, fields),
+                };
+                let args = fields.iter()
+                    .flat_map(|(k, v)| [Sexp::Atom(format!(":{k}")), Sexp::from(v)]);
+                Sexp::List(tag.into_iter().chain(args).collect())
+            }
+        }
+    }
+}
+
+impl TryFrom<&Sexp> for Json {
+    type Error = DecodeError;
+
+    fn try_from(sexp: &Sexp) -> Result<Json, DecodeError> {
+        let shape = |expected: &'static str, found: &str| DecodeError::Shape { expected, found: found.to_string() };
+        match sexp {
+            Sexp::String(s) => Ok(Json::String(s.clone())),
+            Sexp::Atom(a) => match a.as_str() {
+                "#nil" => Ok(Json::Null),
+                "#t" => Ok(Json::Bool(true)),
+                "#f" => Ok(Json::Bool(false)),
+                "#{}" => Ok(Json::Object(vec![])),
+                a => a.parse().map(Json::Number).map_err(|_| shape("a number", a)),
+            },
+            Sexp::List(items) => {
+                let (kind, args) = match &items[..] {
+                    [Sexp::Atom(tag), rest @ ..] if is_symbol(tag) => (Some(tag), rest),
+                    [Sexp::Atom(k), ..] if k.starts_with(':') => (None, &items[..]),
+                    _ => return items.iter().map(Json::try_from).collect::<Result<_, _>>().map(Json::Array),
+                };
+
+                let mut fields = kind.iter()
+                    .map(|tag| ("kind".to_string(), Json::String(tag.to_string())))
+                    .collect::<Vec<_>>();
+                for pair in args.chunks(2) {
+                    let [Sexp::Atom(k), v] = pair else {
+                        return Err(shape("a keyword followed by a value", &pair[0].to_string()));
+                    };
+                    let Some(k) = k.strip_prefix(':') else {
+                        return Err(shape("a keyword", k));
+                    };
+                    fields.push((k.to_string(), Json::try_from(v)?));
+                }
+                Ok(Json::Object(fields))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Sexp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Sexp::Atom(a) => write!(f, "{a}"),
+            Sexp::String(s) => write_string(f, s),
+            Sexp::List(items) => {
+                write!(f, "(")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 { write!(f, " ")?; }
+                    write!(f, "{item}")?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Sexp {
+    type Err = DecodeError;
+
+    fn from_str(s: &str) -> Result<Sexp, DecodeError> {
+        let (sexp, pos) = parse(s, 0, 0)?;
+        let pos = skip_whitespace(s, pos);
+        match pos == s.len() {
+            true => Ok(sexp),
+            false => Err(DecodeError::Sexp { msg: "trailing characters", pos }),
+        }
+    }
+}
+
+fn skip_whitespace(src: &str, pos: usize) -> usize {
+    src.len() - src[pos..].trim_start().len()
+}
+
+/* Parses the S-expression starting at 'pos' and returns it along with the
+ * position right after it. 'depth' is the number of lists it's inside of. */
+fn parse(src: &str, pos: usize, depth: usize) -> Result<(Sexp, usize), DecodeError> {
+    let pos = skip_whitespace(src, pos);
+    match src[pos..].chars().next() {
+        None => Err(DecodeError::Sexp { msg: "expected an S-expression", pos }),
+        Some(')') => Err(DecodeError::Sexp { msg: "unbalanced ')'", pos }),
+        Some('"') => {
+            let (s, end) = parse_string(src, pos).map_err(|err| match err {
+                DecodeError::Json { msg, pos } => DecodeError::Sexp { msg, pos },
+                err => err,
+            })?;
+            Ok((Sexp::String(s), end))
+        }
+        Some('(') if depth == MAX_DEPTH => Err(DecodeError::Sexp { msg: "nested too deeply", pos }),
+        Some('(') => {
+            let mut items = vec![];
+            let mut pos = pos + 1;
+            loop {
+                pos = skip_whitespace(src, pos);
+                match src[pos..].chars().next() {
+                    None => return Err(DecodeError::Sexp { msg: "unclosed '('", pos }),
+                    Some(')') => return Ok((Sexp::List(items), pos + 1)),
+                    Some(_) => {
+                        let (item, end) = parse(src, pos, depth + 1)?;
+                        items.push(item);
+                        pos = end;
+                    }
+                }
+            }
+        }
+        Some(_) => {
+            let len = src[pos..].find(|c| !is_atom_char(c)).unwrap_or(src.len() - pos);
+            Ok((Sexp::Atom(src[pos..pos + len].to_string()), pos + len))
+        }
+    }
+}
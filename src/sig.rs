@@ -0,0 +1,118 @@
+/*  Signatures list the types of a program's declarations, e.g.
+ *
+ *      id : forall a. a -> a
+ *      b : Bool
+ *
+ *  Signature files use the same format, with blank lines and lines starting
+ *  with '#' ignored. */
+
+use crate::typ::{PolyType, TypeParseError, parse_type};
+use crate::typck::TypeError;
+
+use thiserror::Error;
+
+use std::sync::Arc;
+
+/// The types of a program's declarations, in the order they were declared.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Signature {
+    pub items: Vec<(Arc<str>, PolyType)>,
+}
+
+/// An error found while parsing a signature, with 1-based line numbers.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SignatureParseError {
+    #[error("Line {line} should look like 'name : type'.")]
+    Malformed { line: usize },
+    #[error("Line {line}: {err}")]
+    Type { line: usize, err: TypeParseError },
+}
+
+impl Signature {
+    /// The type of the latest declaration of 'name'
+    pub fn lookup(&self, name: &str) -> Option<&PolyType> {
+        self.items.iter()
+            .rev()
+            .find(|(n, _)| &**n == name)
+            .map(|(_, t)| t)
+    }
+
+    /// Checks a module's 'inferred' signature against this declared one. Every
+    /// declared name has to be defined by the module, with a type at least as
+    /// general as the declared one. Anything not declared stays private.
+    pub fn check(&self, inferred: &Signature) -> Vec<TypeError> {
+        self.items.iter()
+            .filter_map(|(name, declared)| match inferred.lookup(name) {
+                None => Some(TypeError::NotDefined { name: name.clone() }),
+                Some(t) if !declared.is_instance_of(t) => Some(TypeError::SignatureMismatch {
+                    name: name.clone(),
+                    declared: declared.clone(),
+                    inferred: t.clone(),
+                }),
+                Some(_) => None,
+            })
+            .collect()
+    }
+}
+
+impl std::str::FromStr for Signature {
+    type Err = SignatureParseError;
+
+    fn from_str(s: &str) -> Result<Signature, SignatureParseError> {
+        let mut items = vec![];
+        for (i, text) in s.lines().enumerate() {
+            let line = i + 1;
+            let text = text.trim();
+            if text.is_empty() || text.starts_with('#') { continue; }
+
+            let Some((name, t)) = text.split_once(':') else {
+                return Err(SignatureParseError::Malformed { line });
+            };
+            let name = name.trim();
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                return Err(SignatureParseError::Malformed { line });
+            }
+            let t = parse_type(t.trim()).map_err(|err| SignatureParseError::Type { line, err })?;
+            items.push((name.into(), t));
+        }
+        Ok(Signature { items })
+    }
+}
+
+impl std::fmt::Display for Signature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (name, t) in &self.items {
+            writeln!(f, "{name} : {t}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let inferred = "id : forall a. a -> a\nk : forall a b. a -> b -> a\nt : forall a. a -> Bool\n"
+            .parse::<Signature>()
+            .unwrap();
+        assert_eq!(inferred.to_string().parse(), Ok(inferred.clone()));
+
+        let declared = "# the identity\nid : Bool -> Bool\n\nk : forall a. a -> a -> a\n".parse::<Signature>().unwrap();
+        assert!(declared.check(&inferred).is_empty());
+
+        let declared = "t : forall a. a -> a\nf : Bool".parse::<Signature>().unwrap();
+        let errors = declared.check(&inferred).iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        assert_eq!(errors, [
+            "t is declared as forall a. a -> a, but its inferred type forall a. a -> Bool isn't as general.",
+            "f is declared in the signature but never defined.",
+        ]);
+
+        assert_eq!("id : forall a. a -> b".parse::<Signature>(), Err(SignatureParseError::Type {
+            line: 1,
+            err: TypeParseError::UnquantifiedVar { name: "b".to_string(), pos: 15 },
+        }));
+        assert_eq!("id forall".parse::<Signature>(), Err(SignatureParseError::Malformed { line: 1 }));
+    }
+}
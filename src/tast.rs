@@ -0,0 +1,123 @@
+/*  The typed AST, which mirrors ast::Expression except that every node also
+ *  carries the type that was inferred for it.
+ *
+ *  While inference is running the types are still MonoTypes whose variables
+ *  can get bound later on (a LiveExpression). Once inference is done the tree
+ *  gets zonked into a TypedExpression, which only holds resolved types. */
+
+use crate::typ::{PolyType, Scheme, MonoType, Type};
+
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// An expression along with the type that was inferred for it, see
+/// infer_typed.
+#[derive(Debug)]
+pub struct TypedExpression<T = Arc<Type>, P = PolyType> {
+    pub kind: TypedKind<T, P>,
+    pub typ: T,
+}
+
+/// The kinds of expression in the typed AST, one for each ast::Expression.
+#[derive(Debug)]
+pub enum TypedKind<T = Arc<Type>, P = PolyType> {
+    // just a variable
+    Var { name: Arc<str> },
+
+    // function application: f e
+    App { f: Box<TypedExpression<T, P>>, e: Box<TypedExpression<T, P>> },
+
+    // lambda abstraction: \x . e
+    Abs { name: Arc<str>, e: Box<TypedExpression<T, P>> },
+
+    // let-in: let x = e0 in e1, where x has the generalized type 'poly'
+    Let {
+        name: Arc<str>,
+        poly: P,
+        e0: Box<TypedExpression<T, P>>,
+        e1: Box<TypedExpression<T, P>>,
+    },
+
+    // booleans
+    True,
+    False,
+
+    // stands in for an expression that failed to parse
+    Error,
+}
+
+/// The typed AST as it is while inference is still running.
+pub type LiveExpression = TypedExpression<Rc<MonoType>, Rc<Scheme>>;
+
+impl<T, P> TypedExpression<T, P> {
+    pub fn new(kind: TypedKind<T, P>, typ: T) -> TypedExpression<T, P> {
+        TypedExpression { kind, typ }
+    }
+}
+
+impl LiveExpression {
+    pub fn zonk(&self) -> TypedExpression {
+        use TypedKind::*;
+        let kind = match &self.kind {
+            Var { name } => Var { name: name.clone() },
+            App { f, e } => App { f: f.zonk().into(), e: e.zonk().into() },
+            Abs { name, e } => Abs { name: name.clone(), e: e.zonk().into() },
+            Let { name, poly, e0, e1 } => Let {
+                name: name.clone(),
+                poly: poly.zonk(),
+                e0: e0.zonk().into(),
+                e1: e1.zonk().into(),
+            },
+            True  => True,
+            False => False,
+            Error => Error,
+        };
+        TypedExpression::new(kind, self.typ.zonk())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typ::VarType;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_zonk() {
+        let var = Rc::new(MonoType::Var { tvar: RefCell::new(VarType::Unbound { id: 0, level: 0 }) });
+        let func = Rc::new(MonoType::Func { l: var.clone(), r: var.clone() });
+        let x = LiveExpression::new(TypedKind::Var { name: "x".into() }, var.clone());
+        let live = LiveExpression::new(TypedKind::Abs { name: "x".into(), e: x.into() }, func);
+
+        let before = live.zonk();
+        assert_eq!(before.typ.to_string(), "'a -> 'a");
+
+        if let MonoType::Var { tvar } = &*var {
+            *tvar.borrow_mut() = VarType::Bound { typ: MonoType::Bool.into() };
+        }
+        let after = live.zonk();
+        assert_eq!(after.typ.to_string(), "Bool -> Bool");
+        let TypedKind::Abs { e, .. } = &after.kind else { panic!("{:?} isn't a lambda", after.kind) };
+        assert_eq!(*e.typ, Type::Bool);
+
+        // a zonked tree is a snapshot that binding the variable didn't change
+        assert_eq!(before.typ.to_string(), "'a -> 'a");
+    }
+
+    #[test]
+    fn test_typed_ast() {
+        let (e, errors) = crate::typck::infer_typed(ast::parse(r"let id = \x. x in id true").unwrap());
+        assert!(errors.is_empty());
+        assert_eq!(*e.typ, Type::Bool);
+
+        let TypedKind::Let { name, poly, e0, e1 } = &e.kind else { panic!("{:?} isn't a let", e.kind) };
+        assert_eq!(&**name, "id");
+        assert_eq!(poly.to_string(), "forall a. a -> a");
+        assert_eq!(e0.typ.to_string(), "'a -> 'a");
+
+        // id is instantiated at Bool where it's used
+        let TypedKind::App { f, e: arg } = &e1.kind else { panic!("{:?} isn't an application", e1.kind) };
+        assert_eq!(f.typ.to_string(), "Bool -> Bool");
+        assert_eq!(*arg.typ, Type::Bool);
+    }
+}
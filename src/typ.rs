@@ -1,131 +1,649 @@
+mod parse;
+pub use parse::{parse_type, TypeParseError};
+
+use crate::ctx::TypeContext;
+
 use std::rc::Rc;
+use std::sync::Arc;
 use std::cell::RefCell;
-use std::collections::HashSet;
+use std::collections::HashMap;
 
+/* A polytype whose body is still being inferred. This is what gets stored in
+ * the TypeContext, since its type variables can still be bound by unify. */
 #[derive(Debug)]
-pub struct PolyType {
-    pub tvar_ids: Rc<[u8]>,
+pub struct Scheme {
+    pub tvar_ids: Rc<[u32]>,
     pub typ: Rc<MonoType>,
 }
 
+impl Scheme {
+    pub fn zonk(&self) -> PolyType {
+        PolyType::new(self.tvar_ids.iter().copied().collect::<Vec<_>>(), self.typ.zonk())
+    }
+
+    /// The unbound type variables that aren't quantified
+    pub fn free_vars(&self) -> Vec<u32> {
+        let mut vars = self.typ.free_vars();
+        vars.retain(|id| !self.tvar_ids.contains(id));
+        vars
+    }
+
+    /* Replaces the quantified variables with 'args', in the order they're
+     * listed in tvar_ids */
+    pub fn instantiate_with(&self, args: &[Rc<MonoType>]) -> Rc<MonoType> {
+        let map = self.tvar_ids.iter()
+            .copied()
+            .zip(args.iter().cloned())
+            .collect();
+        self.typ.substitute(&map)
+    }
+}
+
+/// A fully resolved polytype, which is what inference hands back once it's
+/// done. It's a snapshot of a polytype that was still being inferred, so it
+/// doesn't change anymore and can be sent between threads.
+#[derive(Debug, Clone)]
+pub struct PolyType {
+    pub tvar_ids: Arc<[u32]>,
+    pub typ: Arc<Type>,
+    /// Names that free variables were written with, which parse_type keeps so
+    /// that the type displays the way it was written. Other variables are
+    /// named in order of their first appearance.
+    pub free_names: Arc<[(u32, Arc<str>)]>,
+}
+
+/* The names of free variables only matter for displaying the type */
+impl PartialEq for PolyType {
+    fn eq(&self, other: &PolyType) -> bool {
+        self.tvar_ids == other.tvar_ids && self.typ == other.typ
+    }
+}
+
+impl Eq for PolyType {}
+
+impl PolyType {
+    /// A polytype quantified over 'tvar_ids', whose free variables are named in
+    /// order of their first appearance
+    pub fn new(tvar_ids: impl Into<Arc<[u32]>>, typ: Arc<Type>) -> PolyType {
+        PolyType { tvar_ids: tvar_ids.into(), typ, free_names: [].into() }
+    }
+
+    /// A polytype quantified over every type variable in 'typ', like the type
+    /// of a let declaration once it's generalized
+    pub fn generalize_all(typ: Arc<Type>) -> PolyType {
+        PolyType::new(typ.free_vars(), typ)
+    }
+
+    /// The type variables that aren't quantified, in order of first appearance
+    pub fn free_vars(&self) -> Vec<u32> {
+        let mut vars = self.typ.free_vars();
+        vars.retain(|id| !self.tvar_ids.contains(id));
+        vars
+    }
+
+    /// Replaces the quantified variables with 'args', in the order they're
+    /// listed in tvar_ids
+    pub fn instantiate_with(&self, args: &[Arc<Type>]) -> Arc<Type> {
+        let map = self.tvar_ids.iter()
+            .copied()
+            .zip(args.iter().cloned())
+            .collect();
+        self.typ.substitute(&map)
+    }
+
+    /// Turns the polytype back into one that can be put in 'ctx'. Every type
+    /// variable gets a fresh id from 'ctx', so they can't be mistaken for a
+    /// variable already in it. Type variables with the same id become the same
+    /// variable, but free ones aren't linked to any variable already in the
+    /// context, so this is meant for polytypes without free variables.
+    pub(crate) fn to_scheme(&self, ctx: &mut TypeContext) -> Scheme {
+        let mut vars = HashMap::<u32, Rc<MonoType>>::new();
+        let typ = self.typ.fold(&mut |layer| match layer {
+            TypeLayer::Bool => MonoType::Bool.into(),
+            TypeLayer::Func { l, r } => MonoType::Func { l, r }.into(),
+            TypeLayer::Var { id } => vars.entry(id)
+                .or_insert_with(|| ctx.fresh_variable().into())
+                .clone(),
+            TypeLayer::Error => MonoType::Error.into(),
+        });
+
+        // quantified variables that don't appear in the type don't need an id
+        let tvar_ids = self.tvar_ids.iter()
+            .filter_map(|id| vars.get(id))
+            .flat_map(|t| t.free_vars())
+            .collect();
+        Scheme { tvar_ids, typ }
+    }
+
+    /// Renumbers the quantified variables in order of their first appearance,
+    /// dropping the ones that don't appear at all. Free variables keep their ids,
+    /// so quantified variables are numbered after the largest free one to keep
+    /// the two apart.
+    pub fn canonicalize(&self) -> PolyType {
+        let (bound, free): (Vec<u32>, Vec<u32>) = self.typ.free_vars()
+            .into_iter()
+            .partition(|id| self.tvar_ids.contains(id));
+        let start = free.iter().max().map_or(0, |id| id + 1);
+
+        let tvar_ids = (start..).take(bound.len()).collect::<Vec<_>>();
+        let args = tvar_ids.iter()
+            .map(|id| Type::Var { id: *id }.into())
+            .collect::<Vec<_>>();
+
+        let typ = PolyType::new(bound, self.typ.clone()).instantiate_with(&args);
+        PolyType { tvar_ids: tvar_ids.into(), typ, free_names: self.free_names.clone() }
+    }
+
+    /// Whether both polytypes are the same up to renaming quantified variables,
+    /// e.g. forall a b. a -> b and forall b a. b -> a
+    pub fn alpha_eq(&self, other: &PolyType) -> bool {
+        self.canonicalize() == other.canonicalize()
+    }
+
+    /// Whether 'self' is an instance of 'general', i.e. whether a value of type
+    /// 'general' can be used wherever a 'self' is expected. E.g. Bool -> Bool
+    /// and forall a. (a -> a) -> a -> a are instances of forall a. a -> a, but
+    /// forall a b. a -> b isn't.
+    pub fn is_instance_of(&self, general: &PolyType) -> bool {
+        let mut subst = HashMap::new();
+        match_type(general, &general.typ, self, &self.typ, &mut subst)
+    }
+}
+
+/* One-way unification: binds the quantified variables of 'general' so that it
+ * becomes 'specific'. The quantified variables of 'specific' can't be bound,
+ * they only ever match themselves. An error type doesn't match anything, since
+ * there's no telling what type it should have been. */
+fn match_type(
+    general: &PolyType,
+    g: &Arc<Type>,
+    specific: &PolyType,
+    s: &Arc<Type>,
+    subst: &mut HashMap<u32, Arc<Type>>,
+) -> bool {
+    match (&**g, &**s) {
+        (Type::Error, _) | (_, Type::Error) => false,
+        (Type::Var { id }, _) if general.tvar_ids.contains(id) => match subst.get(id) {
+            Some(t) => t == s,
+            None => {
+                subst.insert(*id, s.clone());
+                true
+            }
+        },
+        (Type::Var { id: a }, Type::Var { id: b }) => a == b && !specific.tvar_ids.contains(b),
+        (Type::Func { l: l_g, r: r_g }, Type::Func { l: l_s, r: r_s }) => {
+            match_type(general, l_g, specific, l_s, subst) && match_type(general, r_g, specific, r_s, subst)
+        }
+        (Type::Bool, Type::Bool) => true,
+        _ => false,
+    }
+}
+
+impl std::str::FromStr for PolyType {
+    type Err = TypeParseError;
+
+    fn from_str(s: &str) -> Result<PolyType, TypeParseError> {
+        parse_type(s)
+    }
+}
+
+/* Quantified variables are named in order of their first appearance and are
+ * written without a quote, while free variables keep the quote of a monotype:
+ * forall a b. a -> 'c -> b */
 impl std::fmt::Display for PolyType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if self.tvar_ids.len() == 0 {
-            return write!(f, "{}", self.typ);
+        let names = TypeNames::for_poly(self);
+        if self.tvar_ids.is_empty() {
+            return write!(f, "{}", names.display(&self.typ));
         }
 
-        let vars = (0..self.tvar_ids.len() as u8)
-            .map(|n| ((b'a' + n) as char).to_string())
+        let vars = names.names.iter()
+            .filter(|(id, _)| self.tvar_ids.contains(id))
+            .map(|(_, name)| name.as_str())
             .collect::<Vec<_>>()
             .join(" ");
 
-        write!(f, "forall {}. {}", vars, self.typ)
+        write!(f, "forall {}. {}", vars, names.display(&self.typ))
     }
 }
 
+/// A monotype that is still being inferred. Its type variables are shared
+/// between every type they appear in, so binding one with unify changes all of
+/// them at once.
 #[derive(Debug, PartialEq, Eq)]
 pub enum MonoType {
     Bool,
     Func { l: Rc<MonoType>, r: Rc<MonoType> },
     Var { tvar: RefCell<VarType> },
+
+    // stands in for the type of an expression that failed to type check
+    Error,
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum VarType {
     Bound { typ: Rc<MonoType> },
-    Unbound { id: u8 },
+    /// 'level' is how many lets deep the variable was made, see
+    /// TypeContext::enter_level.
+    Unbound { id: u32, level: u32 },
 }
 
 impl MonoType {
-    pub fn as_poly(self: Rc<Self>) -> PolyType {
+    pub fn as_scheme(self: Rc<Self>) -> Scheme {
         let tvar_ids = Rc::new([]);
-        PolyType { tvar_ids, typ: self }
+        Scheme { tvar_ids, typ: self }
+    }
+
+    /* Folds the type from the bottom up, following bound type variables to the
+     * type they're bound to. Sub-types are folded left to right. */
+    pub fn fold<B>(&self, f: &mut impl FnMut(TypeLayer<B>) -> B) -> B {
+        let layer = match self {
+            MonoType::Bool => TypeLayer::Bool,
+            MonoType::Func { l, r } => {
+                let l = l.fold(f);
+                let r = r.fold(f);
+                TypeLayer::Func { l, r }
+            }
+            MonoType::Var { tvar } => match &*tvar.borrow() {
+                VarType::Bound { typ }  => return typ.fold(f),
+                VarType::Unbound { id, .. } => TypeLayer::Var { id: *id },
+            }
+            MonoType::Error => TypeLayer::Error,
+        };
+        f(layer)
+    }
+
+    /* Follows every bound type variable through to the type it's bound to,
+     * leaving only the unbound ones. The result is a snapshot, so binding a
+     * type variable later on won't change it. */
+    pub fn zonk(&self) -> Arc<Type> {
+        self.fold(&mut |layer| Type::from(layer).into())
+    }
+
+    /* The unbound type variables, in order of first appearance */
+    pub fn free_vars(&self) -> Vec<u32> {
+        let mut vars = vec![];
+        self.fold(&mut |layer| push_var(&mut vars, layer));
+        vars
+    }
+
+    /// The unbound type variables whose level is above 'level', in order of
+    /// first appearance
+    pub fn vars_above(&self, level: u32) -> Vec<u32> {
+        fn go(t: &MonoType, level: u32, vars: &mut Vec<u32>) {
+            match t {
+                MonoType::Func { l, r } => {
+                    go(l, level, vars);
+                    go(r, level, vars);
+                }
+                MonoType::Var { tvar } => match &*tvar.borrow() {
+                    VarType::Bound { typ } => go(typ, level, vars),
+                    VarType::Unbound { id, level: l } => if *l > level && !vars.contains(id) {
+                        vars.push(*id);
+                    }
+                }
+                MonoType::Bool | MonoType::Error => {}
+            }
+        }
+
+        let mut vars = vec![];
+        go(self, level, &mut vars);
+        vars
+    }
+
+    /* Replaces the unbound type variables found in 'map'. The ones that aren't
+     * in 'map' are kept as they are, so they can still be bound by unify. */
+    pub fn substitute(self: &Rc<Self>, map: &HashMap<u32, Rc<MonoType>>) -> Rc<MonoType> {
+        match &**self {
+            MonoType::Func { l, r } => {
+                let l = l.substitute(map);
+                let r = r.substitute(map);
+                MonoType::Func { l, r }.into()
+            },
+            MonoType::Var { tvar } => match &*tvar.borrow() {
+                VarType::Bound { typ }  => typ.substitute(map),
+                VarType::Unbound { id, .. } => match map.get(id) {
+                    Some(t) => t.clone(),
+                    None => self.clone(),
+                }
+            }
+            MonoType::Bool | MonoType::Error => self.clone(),
+        }
     }
 }
 
 impl std::fmt::Display for MonoType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut unbound_vars = HashSet::new();
-        find_vars(&mut unbound_vars, self);
+        write!(f, "{}", self.zonk())
+    }
+}
 
-        let mut unbound_vars = unbound_vars.into_iter().collect::<Vec<_>>();
-        unbound_vars.sort();
+/// A monotype with all of its bound type variables resolved, see
+/// MonoType::zonk.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Type {
+    Bool,
+    Func { l: Arc<Type>, r: Arc<Type> },
+    Var { id: u32 },
+
+    // stands in for the type of an expression that failed to type check
+    Error,
+}
 
-        let disp_type = DisplayType::new(self, &unbound_vars);
-        write!(f, "{disp_type}")
+/* A single layer of a type whose sub-types have already been folded into 'B',
+ * see Type::fold and MonoType::fold. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeLayer<B> {
+    Bool,
+    Func { l: B, r: B },
+    Var { id: u32 },
+    Error,
+}
+
+impl From<TypeLayer<Arc<Type>>> for Type {
+    fn from(layer: TypeLayer<Arc<Type>>) -> Type {
+        match layer {
+            TypeLayer::Bool => Type::Bool,
+            TypeLayer::Func { l, r } => Type::Func { l, r },
+            TypeLayer::Var { id } => Type::Var { id },
+            TypeLayer::Error => Type::Error,
+        }
+    }
+}
+
+fn push_var<B>(vars: &mut Vec<u32>, layer: TypeLayer<B>) {
+    if let TypeLayer::Var { id } = layer && !vars.contains(&id) {
+        vars.push(id);
+    }
+}
+
+impl Type {
+    /* Folds the type from the bottom up. Sub-types are folded left to right. */
+    pub fn fold<B>(&self, f: &mut impl FnMut(TypeLayer<B>) -> B) -> B {
+        let layer = match self {
+            Type::Bool => TypeLayer::Bool,
+            Type::Func { l, r } => {
+                let l = l.fold(f);
+                let r = r.fold(f);
+                TypeLayer::Func { l, r }
+            }
+            Type::Var { id } => TypeLayer::Var { id: *id },
+            Type::Error => TypeLayer::Error,
+        };
+        f(layer)
+    }
+
+    /* The type variables, in order of first appearance */
+    pub fn free_vars(&self) -> Vec<u32> {
+        let mut vars = vec![];
+        self.fold(&mut |layer| push_var(&mut vars, layer));
+        vars
+    }
+
+    /* Whether the error type appears anywhere in the type, which makes it the
+     * type of something that didn't type check */
+    pub fn has_error(&self) -> bool {
+        self.fold(&mut |layer| match layer {
+            TypeLayer::Func { l, r } => l || r,
+            TypeLayer::Error => true,
+            TypeLayer::Bool | TypeLayer::Var { .. } => false,
+        })
+    }
+
+    /* Replaces the type variables found in 'map' */
+    pub fn substitute(&self, map: &HashMap<u32, Arc<Type>>) -> Arc<Type> {
+        self.fold(&mut |layer| match layer {
+            TypeLayer::Var { id } if map.contains_key(&id) => map[&id].clone(),
+            layer => Type::from(layer).into(),
+        })
+    }
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names = TypeNames::new([self]);
+        write!(f, "{}", names.display(self))
     }
 }
 
-fn find_vars(unbound_vars: &mut HashSet<u8>, typ: &MonoType) {
-    match typ {
-        MonoType::Bool => {},
-        MonoType::Func { l, r } => {
-            find_vars(unbound_vars, l);
-            find_vars(unbound_vars, r);
+/* Names the type variables found across several types. Types that are
+ * displayed through the same TypeNames agree on which variable is 'a, 'b, etc.,
+ * which matters when more than one type is shown in the same message.
+ *
+ * Variables are named in order of their first appearance. */
+pub struct TypeNames {
+    // every variable along with its name, in order of first appearance
+    names: Vec<(u32, String)>,
+}
+
+impl TypeNames {
+    /* Names every variable in 'types' as a free variable */
+    pub fn new<'typ>(types: impl IntoIterator<Item = &'typ Type>) -> TypeNames {
+        let mut vars = vec![];
+        for typ in types {
+            for id in typ.free_vars() {
+                if !vars.contains(&id) { vars.push(id); }
+            }
         }
-        MonoType::Var { tvar } => match &*tvar.borrow() {
-            VarType::Bound { typ }  => find_vars(unbound_vars, typ),
-            VarType::Unbound { id } => { unbound_vars.insert(*id); },
+        TypeNames::with_bound(vars, &[], &[])
+    }
+
+    /* Names the variables of a polytype, where the quantified variables are
+     * named without a quote */
+    pub fn for_poly(poly: &PolyType) -> TypeNames {
+        let mut vars = poly.typ.free_vars();
+
+        // quantified variables that don't show up in the type still need a name
+        for id in poly.tvar_ids.iter() {
+            if !vars.contains(id) { vars.push(*id); }
         }
+
+        TypeNames::with_bound(vars, &poly.tvar_ids, &poly.free_names)
+    }
+
+    fn with_bound(vars: Vec<u32>, bound: &[u32], given: &[(u32, Arc<str>)]) -> TypeNames {
+        // generated names skip the ones that were given, so that they can't clash
+        let mut generated = (0..)
+            .map(var_name)
+            .filter(|name| !given.iter().any(|(_, n)| **n == **name));
+
+        let names = vars.into_iter()
+            .map(|id| match given.iter().find(|(id_, _)| *id_ == id) {
+                Some((_, name)) if !bound.contains(&id) => (id, format!("'{name}")),
+                _ => {
+                    let name = generated.next().unwrap();
+                    match bound.contains(&id) {
+                        true  => (id, name),
+                        false => (id, format!("'{name}")),
+                    }
+                }
+            })
+            .collect();
+
+        TypeNames { names }
+    }
+
+    pub fn display<'typ>(&'typ self, typ: &'typ Type) -> DisplayType<'typ> {
+        DisplayType::new(typ, self)
+    }
+
+    fn name(&self, id: u32) -> &str {
+        self.names.iter()
+            .find(|(id_, _)| *id_ == id)
+            .map(|(_, name)| name.as_str())
+            .unwrap_or_else(|| panic!("Type variable of id {id} has no name"))
     }
 }
 
-struct DisplayType<'typ> {
-    typ: &'typ MonoType,
-    // must be sorted + no duplicates
-    unbound_vars: &'typ [u8],
+/* a, b, ..., z, a1, b1, ..., z1, a2, ... */
+fn var_name(n: usize) -> String {
+    let c = (b'a' + (n % 26) as u8) as char;
+    match n / 26 {
+        0 => c.to_string(),
+        i => format!("{c}{i}"),
+    }
+}
+
+/// A type displayed through some TypeNames, see TypeNames::display.
+pub struct DisplayType<'typ> {
+    typ: &'typ Type,
+    names: &'typ TypeNames,
 }
 
 impl<'typ> DisplayType<'typ> {
-    fn new(typ: &'typ MonoType, unbound_vars: &'typ [u8]) -> DisplayType<'typ> {
-        DisplayType { typ, unbound_vars }
+    fn new(typ: &'typ Type, names: &'typ TypeNames) -> DisplayType<'typ> {
+        DisplayType { typ, names }
     }
 }
 
 impl std::fmt::Display for DisplayType<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.typ {
-            MonoType::Func { l, r } => {
-                let mut l = l.clone();
-                while let MonoType::Var { tvar } = &*l.clone()
-                   && let VarType::Bound { typ } = &*tvar.borrow()
-                {
-                    l = typ.clone();
-                    continue;
-                }
-
-                let r = DisplayType::new(r, self.unbound_vars);
-                match &*l {
-                    MonoType::Func { l: l_l, r: l_r } => {
-                        let l_l = DisplayType::new(l_l, self.unbound_vars);
-                        let l_r = DisplayType::new(l_r, self.unbound_vars);
-                        write!(f, "({l_l} -> {l_r}) -> {r}")
+            Type::Func { l, r } => {
+                let r = DisplayType::new(r, self.names);
+                match &**l {
+                    l @ Type::Func { .. } => {
+                        let l = DisplayType::new(l, self.names);
+                        write!(f, "({l}) -> {r}")
                     }
                     l => {
-                        let l = DisplayType::new(l, self.unbound_vars);
+                        let l = DisplayType::new(l, self.names);
                         write!(f, "{l} -> {r}")
                     }
                 }
             }
 
-            MonoType::Var { tvar } => match &*tvar.borrow() {
-                VarType::Bound { typ } => {
-                    let typ = DisplayType::new(typ, self.unbound_vars);
-                    write!(f, "{}", typ)
-                }
+            Type::Var { id } => write!(f, "{}", self.names.name(*id)),
+            Type::Bool => write!(f, "Bool"),
+            Type::Error => write!(f, "<error>"),
+        }
+    }
+}
 
-                VarType::Unbound { id } => {
-                    let offset = self.unbound_vars.iter()
-                        .position(|n| n == id)
-                        .expect(format!("Unbound variable of id {id} not found in list").as_str());
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                    // Hopefully there won't be more than 26 unbound variables
-                    let char_id = (b'a' + offset as u8) as char;
-                    write!(f, "'{char_id}")
-                }
-            }
+    fn var(id: u32) -> Arc<Type> {
+        Type::Var { id }.into()
+    }
 
-            MonoType::Bool => write!(f, "Bool"),
+    fn func(l: Arc<Type>, r: Arc<Type>) -> Arc<Type> {
+        Type::Func { l, r }.into()
+    }
+
+    fn mono_var(id: u32) -> Rc<MonoType> {
+        MonoType::Var { tvar: RefCell::new(VarType::Unbound { id, level: 0 }) }.into()
+    }
+
+    fn bind(t: &MonoType, typ: Rc<MonoType>) {
+        if let MonoType::Var { tvar } = t {
+            *tvar.borrow_mut() = VarType::Bound { typ };
         }
     }
+
+    #[test]
+    fn test_fold() {
+        // (a -> Bool) -> b
+        let t = func(func(var(0), Type::Bool.into()), var(1));
+        let size = t.fold(&mut |layer| match layer {
+            TypeLayer::Func { l, r } => 1 + l + r,
+            _ => 1,
+        });
+        assert_eq!(size, 5);
+
+        // layers come bottom up and left to right
+        let mut order = vec![];
+        t.fold(&mut |layer: TypeLayer<()>| order.push(match layer {
+            TypeLayer::Func { .. } => "->".to_string(),
+            TypeLayer::Var { id } => id.to_string(),
+            TypeLayer::Bool => "Bool".to_string(),
+            TypeLayer::Error => "<error>".to_string(),
+        }));
+        assert_eq!(order, ["0", "Bool", "->", "1", "->"]);
+    }
+
+    #[test]
+    fn test_fold_follows_bindings() {
+        let a = mono_var(0);
+        let t = Rc::new(MonoType::Func { l: a.clone(), r: a.clone() });
+        bind(&a, Rc::new(MonoType::Func { l: MonoType::Bool.into(), r: mono_var(1) }));
+
+        let depth = t.fold(&mut |layer: TypeLayer<u32>| match layer {
+            TypeLayer::Func { l, r } => 1 + l.max(r),
+            _ => 0,
+        });
+        assert_eq!(depth, 2);
+        assert_eq!(t.zonk().to_string(), "(Bool -> 'a) -> Bool -> 'a");
+    }
+
+    #[test]
+    fn test_free_vars() {
+        let t = func(var(3), func(var(1), func(var(3), var(2))));
+        assert_eq!(t.free_vars(), [3, 1, 2]);
+        assert_eq!(Type::Bool.free_vars(), []);
+
+        // bound variables aren't free, but whatever they're bound to can be
+        let a = mono_var(0);
+        let t = Rc::new(MonoType::Func { l: a.clone(), r: mono_var(1) });
+        assert_eq!(t.free_vars(), [0, 1]);
+        bind(&a, mono_var(2));
+        assert_eq!(t.free_vars(), [2, 1]);
+
+        let poly = PolyType::new([1], func(var(0), var(1)));
+        assert_eq!(poly.free_vars(), [0]);
+    }
+
+    #[test]
+    fn test_has_error() {
+        assert!(!func(var(0), Type::Bool.into()).has_error());
+        assert!(func(var(0), func(Type::Error.into(), var(1))).has_error());
+    }
+
+    #[test]
+    fn test_is_instance_of() {
+        let poly = |src| parse_type(src).unwrap();
+        assert!(poly("Bool -> Bool").is_instance_of(&poly("forall a. a -> a")));
+        assert!(poly("forall a. (a -> a) -> a -> a").is_instance_of(&poly("forall a. a -> a")));
+        assert!(!poly("forall a b. a -> b").is_instance_of(&poly("forall a. a -> a")));
+
+        let error = PolyType::new([], func(Type::Error.into(), Type::Bool.into()));
+        assert!(!poly("Bool -> Bool").is_instance_of(&error));
+        assert!(!error.is_instance_of(&poly("forall a. a -> Bool")));
+    }
+
+    #[test]
+    fn test_substitute() {
+        let t = func(var(0), func(var(1), var(0)));
+        let map = HashMap::from([(0, func(Type::Bool.into(), var(2)))]);
+        assert_eq!(t.substitute(&map).to_string(), "(Bool -> 'a) -> 'b -> Bool -> 'a");
+        assert_eq!(t.substitute(&HashMap::new()), t);
+
+        // substituting goes through bound variables to the ones they're bound to
+        let a = mono_var(0);
+        let t = Rc::new(MonoType::Func { l: a.clone(), r: mono_var(1) });
+        bind(&a, mono_var(1));
+        let map = HashMap::from([(1, Rc::new(MonoType::Bool))]);
+        assert_eq!(t.substitute(&map).zonk().to_string(), "Bool -> Bool");
+
+        let poly = PolyType::new([0, 1], func(var(0), func(var(1), var(2))));
+        let t = poly.instantiate_with(&[Type::Bool.into(), var(5)]);
+        assert_eq!(t.to_string(), "Bool -> 'a -> 'b");
+        assert_eq!(t.free_vars(), [5, 2]);
+    }
+
+    #[test]
+    fn test_to_scheme() {
+        let mut ctx = TypeContext::new();
+        let a = Rc::new(ctx.fresh_variable());
+
+        // the polytype's ids are taken by 'a and whatever comes next, so the
+        // scheme gets fresh ones instead
+        let scheme = parse_type("forall a b. a -> 'c -> a").unwrap().to_scheme(&mut ctx);
+        assert_eq!(*scheme.tvar_ids, [1]);
+        assert_eq!(scheme.typ.free_vars(), [1, 2]);
+        assert_eq!(scheme.free_vars(), [2]);
+        assert_eq!(ctx.fresh_variable().free_vars(), [3]);
+        assert_eq!(a.free_vars(), [0]);
+    }
 }
@@ -0,0 +1,248 @@
+/*  Parses types written the way PolyType and Type display them, e.g.
+ *
+ *      forall a b. (a -> b) -> 'c -> b
+ *
+ *  Quantified variables are written without a quote and have to be listed in
+ *  the forall, free variables are written with one. Type variables get their
+ *  ids in order of first appearance, starting with the quantified ones. */
+
+use crate::typ::{PolyType, Type};
+
+use thiserror::Error;
+
+use std::sync::Arc;
+
+/// An error found while parsing a type with parse_type.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TypeParseError {
+    #[error("Unexpected '{found}' found at position {pos}, expecting {expected}.")]
+    UnexpectedToken { found: String, expected: &'static str, pos: usize },
+    #[error("Type variable {name} at position {pos} isn't quantified. Free type variables are written as '{name}.")]
+    UnquantifiedVar { name: String, pos: usize },
+    #[error("Type variable {name} at position {pos} is quantified more than once.")]
+    DuplicateVar { name: String, pos: usize },
+}
+
+impl TypeParseError {
+    /// Everything UnexpectedToken can say the parser was expecting.
+    pub const EXPECTED: [&'static str; 4] = ["'.'", "')'", "End of File", "a type"];
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Forall,
+    Dot,
+    Arrow,
+    LParen,
+    RParen,
+    Bool,
+    Error,
+    Var(String),
+    FreeVar(String),
+    Eof,
+    Invalid(char),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Forall  => write!(f, "forall"),
+            Token::Dot     => write!(f, "."),
+            Token::Arrow   => write!(f, "->"),
+            Token::LParen  => write!(f, "("),
+            Token::RParen  => write!(f, ")"),
+            Token::Bool    => write!(f, "Bool"),
+            Token::Error   => write!(f, "<error>"),
+            Token::Var(name)     => write!(f, "{name}"),
+            Token::FreeVar(name) => write!(f, "'{name}"),
+            Token::Eof     => write!(f, "End of File"),
+            Token::Invalid(c)    => write!(f, "{c}"),
+        }
+    }
+}
+
+struct TypeParser<'src> {
+    src: &'src str,
+    pos: usize,
+    // (name, id, is quantified) for every type variable seen so far
+    vars: Vec<(String, u32, bool)>,
+}
+
+impl TypeParser<'_> {
+    fn peek(&self) -> (Token, usize) {
+        let rest = &self.src[self.pos..];
+        let trimmed = rest.trim_start();
+        let pos = self.pos + (rest.len() - trimmed.len());
+
+        let is_name = |c: char| c.is_alphanumeric() || c == '_';
+        let name = |s: &str| s.chars().take_while(|c| is_name(*c)).collect::<String>();
+
+        let tok = match trimmed.chars().next() {
+            None => Token::Eof,
+            Some('.') => Token::Dot,
+            Some('(') => Token::LParen,
+            Some(')') => Token::RParen,
+            Some('-') if trimmed.starts_with("->") => Token::Arrow,
+            Some('<') if trimmed.starts_with("<error>") => Token::Error,
+            Some('\'') => match name(&trimmed[1..]) {
+                n if n.is_empty() => Token::Invalid('\''),
+                n => Token::FreeVar(n),
+            },
+            Some(c) if c.is_alphabetic() => match name(trimmed).as_str() {
+                "forall" => Token::Forall,
+                "Bool"   => Token::Bool,
+                n => Token::Var(n.to_string()),
+            },
+            Some(c) => Token::Invalid(c),
+        };
+        (tok, pos)
+    }
+
+    fn next(&mut self) -> (Token, usize) {
+        let (tok, pos) = self.peek();
+        let len = match &tok {
+            Token::Eof => 0,
+            Token::Invalid(c) => c.len_utf8(),
+            tok => tok.to_string().len(),
+        };
+        self.pos = pos + len;
+        (tok, pos)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), TypeParseError> {
+        let (tok, pos) = self.next();
+        if tok == expected { return Ok(()); }
+
+        Err(TypeParseError::UnexpectedToken {
+            found: tok.to_string(),
+            expected: match expected {
+                Token::Dot    => "'.'",
+                Token::RParen => "')'",
+                _ => "End of File",
+            },
+            pos,
+        })
+    }
+
+    fn var(&mut self, name: String, quantified: bool) -> u32 {
+        if let Some((_, id, _)) = self.vars.iter().find(|(n, _, q)| *n == name && *q == quantified) {
+            return *id;
+        }
+        let id = self.vars.len() as u32;
+        self.vars.push((name, id, quantified));
+        id
+    }
+}
+
+/// Parses a type in the same syntax that PolyType is displayed in, so that
+/// printing a type and parsing it back gives the same type.
+pub fn parse_type(src: &str) -> Result<PolyType, TypeParseError> {
+    let mut p = TypeParser { src, pos: 0, vars: vec![] };
+
+    let mut tvar_ids = vec![];
+    if p.peek().0 == Token::Forall {
+        p.next();
+        while let (Token::Var(name), pos) = p.peek() {
+            p.next();
+            if p.vars.iter().any(|(n, _, _)| *n == name) {
+                return Err(TypeParseError::DuplicateVar { name, pos });
+            }
+            tvar_ids.push(p.var(name, true));
+        }
+        p.expect(Token::Dot)?;
+    }
+
+    let typ = parse_func(&mut p)?;
+    p.expect(Token::Eof)?;
+
+    // free variables keep the names they were written with
+    let free_names = p.vars.into_iter()
+        .filter(|(_, _, quantified)| !quantified)
+        .map(|(name, id, _)| (id, name.into()))
+        .collect();
+
+    Ok(PolyType { tvar_ids: tvar_ids.into(), typ, free_names })
+}
+
+// t -> t -> t is right associative, so the right hand side is parsed recursively
+fn parse_func(p: &mut TypeParser) -> Result<Arc<Type>, TypeParseError> {
+    let l = parse_atom(p)?;
+    if p.peek().0 != Token::Arrow {
+        return Ok(l);
+    }
+    p.next();
+    let r = parse_func(p)?;
+    Ok(Type::Func { l, r }.into())
+}
+
+fn parse_atom(p: &mut TypeParser) -> Result<Arc<Type>, TypeParseError> {
+    let (tok, pos) = p.next();
+    let typ = match tok {
+        Token::Bool  => Type::Bool,
+        Token::Error => Type::Error,
+
+        Token::LParen => {
+            let typ = parse_func(p)?;
+            p.expect(Token::RParen)?;
+            return Ok(typ);
+        }
+
+        Token::Var(name) => {
+            if !p.vars.iter().any(|(n, _, q)| *n == name && *q) {
+                return Err(TypeParseError::UnquantifiedVar { name, pos });
+            }
+            Type::Var { id: p.var(name, true) }
+        }
+
+        Token::FreeVar(name) => Type::Var { id: p.var(name, false) },
+
+        tok => return Err(TypeParseError::UnexpectedToken {
+            found: tok.to_string(),
+            expected: "a type",
+            pos,
+        }),
+    };
+    Ok(typ.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let types = [
+            "Bool",
+            "'a -> 'b",
+            "forall a. a -> a",
+            "forall a b. (a -> b) -> a -> b",
+            "forall a. ((Bool -> a) -> Bool -> a) -> (Bool -> a) -> a",
+            "forall a. a -> 'b -> <error>",
+            "'c -> 'b",
+            "forall a. 'c -> a",
+            "forall b. 'a -> b",
+        ];
+
+        for src in types {
+            let t = parse_type(src).unwrap();
+            assert_eq!(t.to_string(), src);
+            assert_eq!(parse_type(&t.to_string()), Ok(t));
+        }
+    }
+
+    #[test]
+    fn test_unquantified() {
+        assert_eq!(
+            parse_type("forall a. a -> b"),
+            Err(TypeParseError::UnquantifiedVar { name: "b".to_string(), pos: 15 }),
+        );
+    }
+
+    #[test]
+    fn test_duplicate_var() {
+        assert_eq!(
+            parse_type("forall a a. a"),
+            Err(TypeParseError::DuplicateVar { name: "a".to_string(), pos: 9 }),
+        );
+    }
+}
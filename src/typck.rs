@@ -12,146 +12,198 @@
  *  - newvar => TypeContext::fresh_variable (ctx.fresh_variable in comments)
  */
 
-use crate::typ::{PolyType, MonoType, VarType};
+use crate::typ::{PolyType, Scheme, MonoType, Type, VarType, TypeNames};
 
 use crate::TypeContext;
+use crate::tast::{TypedExpression, TypedKind, LiveExpression};
 
-use ast::Expression;
+use crate::sig::Signature;
+use crate::module::{self, ModuleError, ModuleLoader};
+
+use ast::{Declaration, Expression, Program};
 use thiserror::Error;
 
-use std::collections::HashMap;
 use std::rc::Rc;
-use std::collections::HashSet;
+use std::sync::Arc;
 
-#[derive(Debug, Error)]
+/// An error found while inferring a type. Inference doesn't stop at the first
+/// one, see infer_tolerant.
+#[derive(Debug, Clone, Error)]
 pub enum TypeError {
-    #[error("Recursive types are not allowed.")]
-    RecursiveType,
-    #[error("Mismatched types {0} and {1}.")]
-    TypeMismatch(Rc<MonoType>, Rc<MonoType>),
-    #[error("Variable {0} not found.")]
-    VarNotFound(Rc<str>),
+    RecursiveType { var: Arc<Type>, typ: Arc<Type>, expr: Expression },
+    TypeMismatch(Arc<Type>, Arc<Type>),
+    VarNotFound { name: Arc<str>, similar: Vec<Arc<str>>, out_of_scope: bool },
+    Import(ModuleError),
+    NotDefined { name: Arc<str> },
+    SignatureMismatch { name: Arc<str>, declared: PolyType, inferred: PolyType },
 }
 
-type Result<T> = std::result::Result<T, TypeError>;
-
-/* Turns a polytype into a monotype by replacing the type variables bounded by
- * the forall into new unbound type variables.
- * E.g. forall b c. a -> b -> c => a -> d -> e */
-fn instantiate(ctx: &mut TypeContext, t: Rc<PolyType>) -> Rc<MonoType> {
-    /* replace each of the unbound type variables in 't' with the new one using
-     * the mapping created below */
-    fn replace(map: &HashMap<u8, Rc<MonoType>>, t: Rc<MonoType>) -> Rc<MonoType> {
-        use MonoType::*;
-        use VarType::*;
-        match &*t {
-            Func { l, r } => {
-                let l = replace(map, l.clone());
-                let r = replace(map, r.clone());
-                Func { l, r }.into()
-            },
-            Var { tvar } => match &*tvar.borrow() {
-                Bound { typ }  => replace(map, typ.clone()),
-                Unbound { id } => match map.get(id) {
-                    Some(t_) => t_.clone(),
-                    None => t.clone(),
+/* Types that show up in the same error share their variable names, otherwise
+ * 'a -> Bool and 'a could end up naming two different variables 'a. */
+impl std::fmt::Display for TypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TypeError::RecursiveType { var, typ, expr } => {
+                let names = TypeNames::new([&**var, &**typ]);
+                let (var, typ) = (names.display(var), names.display(typ));
+                write!(f, "Recursive types are not allowed: {var} ~ {typ}, arising from '{expr}'.")
+            }
+            TypeError::TypeMismatch(t0, t1) => {
+                let names = TypeNames::new([&**t0, &**t1]);
+                let (t0, t1) = (names.display(t0), names.display(t1));
+                write!(f, "Mismatched types {t0} and {t1}.")
+            }
+            TypeError::VarNotFound { name, similar, out_of_scope } => {
+                write!(f, "Variable {name} not found.")?;
+                if let Some((last, rest)) = similar.split_last() {
+                    match rest {
+                        [] => write!(f, " Did you mean {last}?")?,
+                        _  => write!(f, " Did you mean {} or {last}?", rest.join(", "))?,
+                    }
                 }
+                if *out_of_scope {
+                    write!(f, " A binding named {name} exists but is out of scope here.")?;
+                }
+                Ok(())
+            }
+            TypeError::Import(err) => write!(f, "{err}"),
+            TypeError::NotDefined { name } => {
+                write!(f, "{name} is declared in the signature but never defined.")
+            }
+            TypeError::SignatureMismatch { name, declared, inferred } => {
+                write!(f, "{name} is declared as {declared}, but its inferred type {inferred} isn't as general.")
             }
-            _ => t,
         }
     }
+}
+
+type Result<T> = std::result::Result<T, TypeError>;
 
-    /* for each type variable in the polytype, create a mapping between it and a
-     * new unbound type variable */
-    let map = t.tvar_ids.iter()
-        .map(|id| (*id, ctx.fresh_variable().into()))
-        .collect();
+/* Turns a polytype into a monotype by replacing the type variables bounded by
+ * the forall into new unbound type variables.
+ * E.g. forall b c. a -> b -> c => a -> d -> e */
+fn instantiate(ctx: &mut TypeContext, t: Rc<Scheme>) -> Rc<MonoType> {
+    /* for each type variable in the polytype, create a new unbound type
+     * variable to replace it with */
+    let args = t.tvar_ids.iter()
+        .map(|_| ctx.fresh_variable().into())
+        .collect::<Vec<_>>();
 
-    replace(&map, t.typ.clone())
+    t.instantiate_with(&args)
 }
 
 /* Turns a monotype into a polytype by finding all of the unbound type variables
  * in 'typ' and "binding" them to a forall.
- * E.g. a -> b -> c => forall a b c. a -> b -> c */
-fn generalize(typ: Rc<MonoType>) -> PolyType {
-    fn find_vars(vars: &mut HashSet<u8>, t: Rc<MonoType>) {
-        use MonoType::*;
-        use VarType::*;
-        match &*t {
-            Bool => {},
-            Func { l, r } => {
-                find_vars(vars, l.clone());
-                find_vars(vars, r.clone());
-            }
-            Var { tvar } => match &*tvar.borrow() {
-                Bound { typ } => { find_vars(vars, typ.clone()); }
-                Unbound { id } => { vars.insert(*id); },
-            }
+ * E.g. a -> b -> c => forall a b c. a -> b -> c
+ *
+ * Variables that are still free in the ctx are left out, since they stand for
+ * one particular type that isn't known yet. E.g. in \x. let y = x in y true,
+ * y is only as general as x is. Rather than searching the ctx for them, every
+ * variable has a level: the variables made while inferring the right-hand
+ * side of a let are a level deeper than the ctx, and unify lowers the level of
+ * any of them that get tied to a variable from the ctx. So the ones still above
+ * the ctx's level after exit_level are exactly the ones that aren't free in it. */
+fn generalize(ctx: &TypeContext, typ: Rc<MonoType>) -> Scheme {
+    let tvar_ids = typ.vars_above(ctx.level());
+    Scheme { tvar_ids: tvar_ids.into(), typ }
+}
+
+/* Checks whether 't' is the unbound variable 'id', or a variable bound to it */
+fn is_var(id: u32, t: Rc<MonoType>) -> bool {
+    match &*t {
+        MonoType::Var { tvar } => match &*tvar.borrow() {
+            VarType::Bound { typ } => is_var(id, typ.clone()),
+            VarType::Unbound { id: id_, .. } => id == *id_,
         }
+        _ => false,
     }
-
-    let mut vars = HashSet::new();
-    find_vars(&mut vars, typ.clone());
-    let tvar_ids = vars.into_iter().collect();
-
-    PolyType { tvar_ids, typ }
 }
 
-/* Checks whether or not an unbound variable appears in some monotype 't' */
-fn occurs(id: u8, t: Rc<MonoType>) -> bool {
-    use MonoType::*;
-    use VarType::*;
-    match &*t {
-        Bool => false,
-        Func { l, r } => occurs(id, l.clone()) || occurs(id, r.clone()),
-        Var { tvar } => match &*tvar.borrow() {
-            Bound { typ } => occurs(id, typ.clone()),
-            Unbound { id: id_ } => id == *id_,
+/* Checks whether the unbound variable 'id' appears in 't'. Since 'id' is about
+ * to be bound to 't', the other variables in 't' are lowered to the level of
+ * 'id' on the way, see generalize. */
+fn occurs(id: u32, level: u32, t: &MonoType) -> bool {
+    match t {
+        MonoType::Var { tvar } => {
+            match &*tvar.borrow() {
+                VarType::Bound { typ } => return occurs(id, level, typ),
+                VarType::Unbound { id: id_, .. } => if *id_ == id { return true; },
+            }
+            if let VarType::Unbound { level: l, .. } = &mut *tvar.borrow_mut() {
+                *l = (*l).min(level);
+            }
+            false
         }
+        MonoType::Func { l, r } => occurs(id, level, l) || occurs(id, level, r),
+        MonoType::Bool | MonoType::Error => false,
     }
 }
 
 /* Unification "solves" for type variables and checks if two monotypes have the
- * same type */
-fn unify(t0: Rc<MonoType>, t1: Rc<MonoType>) -> Result<()> {
+ * same type. 'e' is the expression that required the two types to be equal and
+ * is only used for error reporting. */
+fn unify(e: &Expression, t0: Rc<MonoType>, t1: Rc<MonoType>) -> Result<()> {
     match (&*t0, &*t1) {
         /*  The only notable case is when 't0' is an type variable is when it is
          *  unbound. If 't0' is unbound, then we should set it to whatever 't1'
          *  is since we're trying to "unify" them. */
         (MonoType::Var { tvar }, _) => {
             match &*tvar.borrow() {
-                VarType::Bound { typ }  => return unify(typ.clone(), t1),
+                VarType::Bound { typ }  => return unify(e, typ.clone(), t1),
 
-                /* We don't want to set a recursive binding of 't0' to itself */
-                VarType::Unbound { id } => if occurs(*id, t1.clone()) { 
-                    return Err(TypeError::RecursiveType);
+                /* 't1' can be another variable that's already bound to 't0',
+                 * which makes them equal already. Otherwise we don't want to
+                 * set a recursive binding of 't0' to itself. */
+                VarType::Unbound { id, level } => if is_var(*id, t1.clone()) {
+                    return Ok(());
+                } else if occurs(*id, *level, &t1) {
+                    return Err(TypeError::RecursiveType {
+                        var: t0.zonk(),
+                        typ: t1.zonk(),
+                        expr: e.clone(),
+                    });
                 }
             }
             *tvar.borrow_mut() = VarType::Bound { typ: t1 };
         }
 
         /* If 't0' isn't a type variable but 't1' is, just swap it around. */
-        (_, MonoType::Var { .. }) => unify(t1, t0)?,
+        (_, MonoType::Var { .. }) => unify(e, t1, t0)?,
 
         /* The types in two function type should match. */
         (MonoType::Func { l: l_a, r: r_a }, MonoType::Func { l: l_b, r: r_b }) => {
-            unify(l_a.clone(), l_b.clone())?;
-            unify(r_a.clone(), r_b.clone())?;
+            unify(e, l_a.clone(), l_b.clone())?;
+            unify(e, r_a.clone(), r_b.clone())?;
         }
 
+        /* The error type has already been reported, so it can stand in for any
+         * type without reporting the same problem again. */
+        (MonoType::Error, _) | (_, MonoType::Error) => {},
+
         /* By now, both types are concrete types and we just have to check if
          * they're equal. */
         (a, b) => if a != b {
-            return Err(TypeError::TypeMismatch(Rc::clone(&t0), Rc::clone(&t1)))
+            return Err(TypeError::TypeMismatch(t0.zonk(), t1.zonk()))
         },
     }
     Ok(())
 }
 
+/* Every node of the typed AST is made here, once its children are done, so
+ * that nodes get numbered in post-order. */
+fn node(ctx: &mut TypeContext, kind: TypedKind<Rc<MonoType>, Rc<Scheme>>, typ: Rc<MonoType>) -> LiveExpression {
+    ctx.next_node();
+    LiveExpression::new(kind, typ)
+}
+
 /* This is the main part of Algorithm J. We closely follow the inference rules.
  * Some names in the inference rules are changed to fit the names in the
- * implementation */
-fn infer_expr(ctx: &mut TypeContext, e: &Expression) -> Result<Rc<MonoType>> {
+ * implementation.
+ *
+ * Errors don't stop inference. They're reported to the ctx and the offending
+ * expression is given the error type, which unifies with anything, so that the
+ * rest of the expression can still be checked. */
+fn infer_expr(ctx: &mut TypeContext, e: &Expression) -> LiveExpression {
     use Expression::*;
     match e {
 
@@ -162,30 +214,36 @@ fn infer_expr(ctx: &mut TypeContext, e: &Expression) -> Result<Rc<MonoType>> {
          */
         Var { name } => {
             let s = ctx.lookup_sym(name);
-            match s {
-                Some(s) => {
-                    let t = Ok(instantiate(ctx, s));
-                    t
+            let t = match s {
+                Some(s) => instantiate(ctx, s),
+                None => {
+                    let similar = ctx.similar_syms(name);
+                    let out_of_scope = ctx.was_in_scope(name);
+                    ctx.report(TypeError::VarNotFound { name: name.clone(), similar, out_of_scope })
                 }
-                None => Err(TypeError::VarNotFound(name.clone())),
-            }
+            };
+            node(ctx, TypedKind::Var { name: name.clone() }, t)
         }
 
         /*  infer ctx f = t0
-         *  infer ctx e = t1
+         *  infer ctx x = t1
          *  t2 = ctx.fresh_variable
          *  unify t0 (t1 -> t2)
          *  -----------------------
          *  infer ctx (f x) = t2
          */
-        App { f, e } => {
-            let t0 = infer_expr(ctx, f)?;
-            let t1 = infer_expr(ctx, e)?;
+        App { f, e: x } => {
+            let f = infer_expr(ctx, f);
+            let x = infer_expr(ctx, x);
+            let (t0, t1) = (f.typ.clone(), x.typ.clone());
             let t2 = Rc::new(ctx.fresh_variable());
-            
+
             let typ_func = MonoType::Func { l: t1, r: t2.clone() }.into();
-            unify(t0, typ_func)?;
-            Ok(t2)
+            let t2 = match unify(e, t0, typ_func) {
+                Ok(())   => t2,
+                Err(err) => ctx.report(err),
+            };
+            node(ctx, TypedKind::App { f: f.into(), e: x.into() }, t2)
         },
 
 
@@ -196,13 +254,14 @@ fn infer_expr(ctx: &mut TypeContext, e: &Expression) -> Result<Rc<MonoType>> {
          */
         Abs { name, e } => {
             let t0 = Rc::new(ctx.fresh_variable());
-            let t0 = Rc::new(t0.as_poly());
+            let t0 = Rc::new(t0.as_scheme());
 
             ctx.insert_sym(name.clone(), t0.clone());
-            let t1 = infer_expr(ctx, e)?;
+            let e = infer_expr(ctx, e);
             ctx.pop_sym();
 
-            Ok(MonoType::Func { l: t0.typ.clone(), r: t1 }.into())
+            let t = MonoType::Func { l: t0.typ.clone(), r: e.typ.clone() }.into();
+            node(ctx, TypedKind::Abs { name: name.clone(), e: e.into() }, t)
         },
 
         /*  infer ctx e0 = t0
@@ -211,22 +270,253 @@ fn infer_expr(ctx: &mut TypeContext, e: &Expression) -> Result<Rc<MonoType>> {
          *  infer ctx (let name = e0 in e1) = t1
          */
         Let { name, e0, e1 } => {
-            let t0 = infer_expr(ctx, e0)?;
-            let t0 = generalize(t0).into();
+            ctx.enter_level();
+            let e0 = infer_expr(ctx, e0);
+            ctx.exit_level();
+            let t0: Rc<Scheme> = generalize(ctx, e0.typ.clone()).into();
 
-            ctx.insert_sym(name.clone(), t0);
-            let t1 = infer_expr(ctx, e1)?;
+            ctx.insert_sym(name.clone(), t0.clone());
+            let e1 = infer_expr(ctx, e1);
             ctx.pop_sym();
 
-            Ok(t1)
+            let t1 = e1.typ.clone();
+            let kind = TypedKind::Let { name: name.clone(), poly: t0, e0: e0.into(), e1: e1.into() };
+            node(ctx, kind, t1)
         },
 
-        True | False => Ok(MonoType::Bool.into())
+        True  => node(ctx, TypedKind::True, MonoType::Bool.into()),
+        False => node(ctx, TypedKind::False, MonoType::Bool.into()),
+
+        /* The parser has already reported this one */
+        Error => node(ctx, TypedKind::Error, MonoType::Error.into()),
     }
 }
 
+/// Infers the type of 'e', failing with the first error found.
 pub fn infer(e: Expression) -> Result<PolyType> {
+    let (t, errors) = infer_tolerant(e);
+    match errors.into_iter().next() {
+        Some(err) => Err(err),
+        None => Ok(t),
+    }
+}
+
+/// Infers the type of 'e' while collecting every error found along the way.
+/// The returned type is only partial if there were any errors, with the error
+/// type standing in for the expressions that failed to type check.
+pub fn infer_tolerant(e: Expression) -> (PolyType, Vec<TypeError>) {
+    let mut ctx = TypeContext::new();
+    let t = infer_in(&mut ctx, &e);
+    (t, ctx.take_errors())
+}
+
+/// Infers the type of 'e' using the symbols that are already bound in 'ctx'.
+/// Errors are collected in 'ctx', see TypeContext::take_errors.
+pub fn infer_in(ctx: &mut TypeContext, e: &Expression) -> PolyType {
+    ctx.enter_level();
+    let e = infer_expr(ctx, e);
+    ctx.exit_level();
+    generalize(ctx, e.typ).zonk()
+}
+
+/* Like infer_in, but also hands back the typed AST of 'e' */
+pub(crate) fn infer_typed_in(ctx: &mut TypeContext, e: &Expression) -> (PolyType, TypedExpression) {
+    ctx.enter_level();
+    let e = infer_expr(ctx, e);
+    ctx.exit_level();
+    (generalize(ctx, e.typ.clone()).zonk(), e.zonk())
+}
+
+/// Infers the declarations of a program in order, where each declaration can
+/// use the ones before it. Every error is collected like in infer_tolerant.
+/// Imports can't be found without a ModuleLoader, see infer_program_with.
+pub fn infer_program(program: &Program) -> (Signature, Vec<TypeError>) {
+    infer_program_with(&mut ModuleLoader::default(), program)
+}
+
+/// Like infer_program, but loads the modules that the program imports with
+/// 'loader'. The signature holds everything the program exports, which is its
+/// top-level lets as well as the contents of its modules as 'Foo.name'.
+pub fn infer_program_with(loader: &mut ModuleLoader, program: &Program) -> (Signature, Vec<TypeError>) {
+    let typed = infer_program_typed(loader, program);
+    let errors = typed.errors.into_iter().map(|(err, _)| err).collect();
+    (typed.signature, errors)
+}
+
+/// Everything inferred about a program, see infer_program_typed.
+#[derive(Debug)]
+pub struct TypedProgram {
+    pub signature: Signature,
+    /// The typed AST of every `let` declaration, including the ones inside
+    /// modules, in the order they appear.
+    pub exprs: Vec<TypedExpression>,
+    /// Every error along with the post-order index of the node it was found
+    /// at, counting across all of 'exprs' like ast::Spans::nodes does. Errors
+    /// that weren't found at a node, like failed imports, have no index.
+    pub errors: Vec<(TypeError, Option<usize>)>,
+}
+
+/// Like infer_program_with, but also keeps the typed AST of every definition
+/// and where every error was found, for tools that need to point at the source.
+pub fn infer_program_typed(loader: &mut ModuleLoader, program: &Program) -> TypedProgram {
+    let mut ctx = TypeContext::new();
+    let mut exprs = vec![];
+    let items = infer_decls(&mut ctx, loader, &program.decls, &mut exprs);
+    TypedProgram { signature: Signature { items }, exprs, errors: ctx.take_located_errors() }
+}
+
+/// Infers the declarations of 'program' using the symbols that are already
+/// bound in 'ctx', and leaves everything they bind in it, like a REPL session
+/// needs. Errors are collected in 'ctx', see TypeContext::take_errors.
+pub fn infer_program_in(ctx: &mut TypeContext, loader: &mut ModuleLoader, program: &Program) -> Signature {
+    let items = infer_decls(ctx, loader, &program.decls, &mut vec![]);
+    Signature { items }
+}
+
+/* Infers 'decls' in order and returns what they export. Everything the
+ * declarations bind stays in the ctx, it's up to the caller to end the scope. */
+fn infer_decls(
+    ctx: &mut TypeContext,
+    loader: &mut ModuleLoader,
+    decls: &[Declaration],
+    exprs: &mut Vec<TypedExpression>,
+) -> Vec<(Arc<str>, PolyType)> {
+    let mut items = vec![];
+    for decl in decls {
+        match decl {
+            Declaration::Let { name, e } => {
+                ctx.enter_level();
+                let e = infer_expr(ctx, e);
+                ctx.exit_level();
+                let t = generalize(ctx, e.typ.clone()).zonk();
+                exprs.push(e.zonk());
+                ctx.insert_poly(name.clone(), &t);
+                items.push((name.clone(), t));
+            }
+
+            /* Imports are only visible to the module doing the importing, so
+             * they're not exported. */
+            Declaration::Import { module } => match loader.load(module) {
+                Ok(sig) => for (name, t) in &sig.items {
+                    ctx.insert_poly(module::qualify(module, name), t);
+                },
+                Err(err) => ctx.report_at(TypeError::Import(err), None),
+            },
+
+            Declaration::Module { name, decls } => {
+                let scope = ctx.scope();
+                let exports = infer_decls(ctx, loader, decls, exprs);
+                ctx.end_scope(scope);
+
+                for (item, t) in exports {
+                    let item = module::qualify(name, &item);
+                    ctx.insert_poly(item.clone(), &t);
+                    items.push((item, t));
+                }
+            }
+        }
+    }
+    items
+}
+
+/// Like infer_tolerant, but keeps the type of every sub-expression around in a
+/// typed AST instead of only returning the type of 'e'.
+pub fn infer_typed(e: Expression) -> (TypedExpression, Vec<TypeError>) {
     let mut ctx = TypeContext::new();
-    let t = infer_expr(&mut ctx, &e)?;
-    Ok(generalize(t))
+    let e = infer_expr(&mut ctx, &e);
+    (e.zonk(), ctx.take_errors())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typ::parse_type;
+
+    fn assert_infers(src: &str, typ: &str) {
+        let t = infer(ast::parse(src).unwrap()).unwrap();
+        let typ = parse_type(typ).unwrap();
+        assert!(t.alpha_eq(&typ), "{src} was inferred as {t}, expected {typ}");
+    }
+
+    #[test]
+    fn test_infer() {
+        assert_infers(r"\x. x", "forall a. a -> a");
+        assert_infers(r"\x. \y. x", "forall a b. a -> b -> a");
+        assert_infers(r"\f. \g. \x. f (g x)", "forall a b c. (a -> b) -> (c -> a) -> c -> b");
+        assert_infers(r"let id = \x. x in id id true", "Bool");
+        assert_infers(r"\f. \x. f (f x) true", "forall a. ((Bool -> a) -> Bool -> a) -> (Bool -> a) -> a");
+    }
+
+    #[test]
+    fn test_let_generalization() {
+        // y is only as general as x, which is one type for the whole lambda
+        assert_infers(r"\x. let y = x in y true", "forall a. (Bool -> a) -> a");
+        assert!(infer(ast::parse(r"\x. let y = x in let z = y true in y y").unwrap()).is_err());
+        // unless it doesn't mention x at all
+        assert_infers(r"\x. let id = \y. y in id id x", "forall a. a -> a");
+        // y's type only gets tied to x's by unify, which makes f as specific as x
+        assert_infers(r"\x. let f = \y. x y in let a = f true in f", "forall a. (Bool -> a) -> Bool -> a");
+        assert_infers(r"let f = \x. let g = \y. x in g in f true false", "Bool");
+    }
+
+    #[test]
+    fn test_error_messages() {
+        // the same variable gets the same name in both types
+        let var = Arc::new(Type::Var { id: 7 });
+        let func = Arc::new(Type::Func { l: var.clone(), r: Type::Bool.into() });
+        let err = TypeError::TypeMismatch(func, var);
+        assert_eq!(err.to_string(), "Mismatched types 'a -> Bool and 'a.");
+    }
+
+    #[test]
+    fn test_recursive_type_message() {
+        let err = infer(ast::parse(r"\f. f (\x. f)").unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "Recursive types are not allowed: 'a ~ ('b -> 'a) -> 'c, arising from 'f \\x. f'.");
+    }
+
+    #[test]
+    fn test_unify() {
+        // b u t unifies the type of u with itself through f's argument, which
+        // used to fail with "Recursive types are not allowed: 'a ~ 'a"
+        let t = infer(ast::parse(r"(\f. \x. f (f x)) (\b. \t. \u. b u t)").unwrap()).unwrap();
+        assert_eq!(t.to_string(), "forall a b. (a -> a -> b) -> a -> a -> b");
+    }
+
+    #[test]
+    fn test_similar_names() {
+        let err = infer(ast::parse(r"\y. x").unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "Variable x not found. Did you mean y?");
+        let err = infer(ast::parse(r"\xs. \ys. \zs. \longer. \x. lnger").unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "Variable lnger not found. Did you mean longer?");
+    }
+
+    #[test]
+    fn test_several_errors() {
+        let (t, errors) = infer_tolerant(ast::parse(r"\x. (true true) (x x) y").unwrap());
+        let errors = errors.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(errors, [
+            "Mismatched types Bool and Bool -> 'a.",
+            "Recursive types are not allowed: 'a ~ 'a -> 'b, arising from 'x x'.",
+            "Variable y not found. Did you mean x?",
+        ]);
+        // checking carried on past each error, so the whole lambda still has a type
+        assert_eq!(t.to_string(), "forall a b. a -> b");
+    }
+
+    #[test]
+    fn test_infer_program() {
+        let program = ast::parse_program("let id = \\x. x\nlet k = \\x. \\y. x;; let b = k true id").unwrap();
+        let (sig, errors) = infer_program(&program);
+        assert!(errors.is_empty());
+        assert_eq!(sig.to_string(), "id : forall a. a -> a\nk : forall a b. a -> b -> a\nb : Bool\n");
+    }
+
+    #[test]
+    fn test_alpha_eq() {
+        let t = parse_type("forall a b. a -> b").unwrap();
+        assert!(t.alpha_eq(&parse_type("forall b a. b -> a").unwrap()));
+        assert!(t.alpha_eq(&parse_type("forall a b. b -> a").unwrap()));
+        assert!(!t.alpha_eq(&parse_type("forall a. a -> a").unwrap()));
+        assert!(!t.alpha_eq(&parse_type("forall a. a -> 'b").unwrap()));
+    }
 }
@@ -0,0 +1,468 @@
+/*  Compiles the typed AST to bytecode for a small stack machine, which runs
+ *  programs without walking the tree and without growing the Rust stack.
+ *
+ *  Lambdas are closure converted: every lambda becomes a function of its own
+ *  taking one argument, and the variables it uses from around it are copied
+ *  into its closure when the closure is made. Inside a function a variable is
+ *  one of
+ *  - a local, which is the argument or a let and lives on the stack at a
+ *    fixed slot above the frame's base
+ *  - a capture, which was copied into the closure being run
+ *  - a global, which holds a declaration of the program
+ *
+ *  Values are plain words without tags. Type checking guarantees that only
+ *  closures ever get called, so the machine never looks at what it holds, and
+ *  the inferred types of the declarations say how to read their words back
+ *  once the program is done: a Bool is 0 or 1 and anything else is a closure
+ *  on the heap. The heap is never collected, it goes away with the machine. */
+
+use crate::compile::{Backend, CompileError, Walker};
+use crate::module::ModuleLoader;
+use crate::tast::{TypedExpression, TypedKind};
+use crate::typ::Type;
+use crate::typck::TypedProgram;
+
+use ast::{Expression, Program};
+
+use std::sync::Arc;
+
+/// A value on the machine, which is either a boolean or the address of a
+/// closure on the heap.
+pub type Word = usize;
+
+/// An instruction, along with how it changes the stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instr {
+    /// Pushes a boolean
+    Bool(bool),
+    /// Pushes the local in the given slot of the current frame
+    Local(u32),
+    /// Pushes one of the variables captured by the closure being run
+    Capture(u32),
+    /// Pushes a global
+    Global(u32),
+    /// Pops a value into a global
+    SetGlobal(u32),
+    /// Pops the captured variables and pushes a closure of the function
+    Closure { func: u32, captures: u32 },
+    /// Pops an argument and a closure, and calls the closure with the argument
+    Call,
+    /// Returns the value on top of the stack to the caller
+    Return,
+    /// Drops the given number of values below the one on top, which ends lets
+    Slide(u32),
+    /// Stops the machine
+    Halt,
+}
+
+impl Instr {
+    // How many values the instruction leaves on the stack, minus how many it takes
+    fn effect(self) -> i64 {
+        match self {
+            Instr::Bool(_) | Instr::Local(_) | Instr::Capture(_) | Instr::Global(_) => 1,
+            Instr::SetGlobal(_) | Instr::Call | Instr::Return => -1,
+            Instr::Closure { captures, .. } => 1 - captures as i64,
+            Instr::Slide(n) => -(n as i64),
+            Instr::Halt => 0,
+        }
+    }
+}
+
+impl std::fmt::Display for Instr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Instr::Bool(b) => write!(f, "bool {b}"),
+            Instr::Local(i) => write!(f, "local {i}"),
+            Instr::Capture(i) => write!(f, "capture {i}"),
+            Instr::Global(i) => write!(f, "global {i}"),
+            Instr::SetGlobal(i) => write!(f, "set_global {i}"),
+            Instr::Closure { func, captures } => write!(f, "closure {func} {captures}"),
+            Instr::Call => write!(f, "call"),
+            Instr::Return => write!(f, "return"),
+            Instr::Slide(n) => write!(f, "slide {n}"),
+            Instr::Halt => write!(f, "halt"),
+        }
+    }
+}
+
+/// A compiled program.
+#[derive(Debug, Default)]
+pub struct Bytecode {
+    /// The code of every function. Function 0 runs the declarations of the
+    /// program in order, and the rest are the program's lambdas.
+    pub funcs: Vec<Vec<Instr>>,
+    /// The number of globals
+    pub globals: usize,
+    /// What the program exports, named like in its Signature, along with the
+    /// global holding each one and its type.
+    pub exports: Vec<(Arc<str>, u32, Arc<Type>)>,
+}
+
+/// Prints the code of every function, one instruction per line.
+impl std::fmt::Display for Bytecode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, code) in self.funcs.iter().enumerate() {
+            writeln!(f, "fn {i}:")?;
+            for instr in code {
+                writeln!(f, "  {instr}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A value read back from the machine. Functions are only words on the
+/// machine, so there's nothing to show of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value {
+    Bool(bool),
+    Function,
+}
+
+/// Prints the same as eval::Value does.
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Function => write!(f, "<function>"),
+        }
+    }
+}
+
+/// Compiles a typed expression into a program that exports its value as `it`.
+pub fn compile_expr(e: &TypedExpression) -> Result<Bytecode, CompileError> {
+    let mut c = Compiler::new();
+    let mut main = Frame::default();
+    c.expr(&mut main, e)?;
+    main.emit(Instr::SetGlobal(0));
+    main.emit(Instr::Halt);
+    c.funcs[0] = main.code;
+    Ok(Bytecode { funcs: c.funcs, globals: 1, exports: vec![("it".into(), 0, e.typ.clone())] })
+}
+
+/// Compiles a program that was inferred with infer_program_typed, along with
+/// the modules it imports, which are found with 'loader'. The program has to
+/// be free of type errors.
+pub fn compile_program(loader: &mut ModuleLoader, program: &Program, typed: &TypedProgram) -> Result<Bytecode, CompileError> {
+    if !typed.errors.is_empty() {
+        return Err(CompileError::Type(typed.errors.iter().map(|(err, _)| err.clone()).collect()));
+    }
+
+    let mut walker = Walker::new(ProgramCompiler { c: Compiler::new(), main: Frame::default() }, loader);
+    let exports = walker.decls(&program.decls, &mut typed.exprs.iter())?;
+    let ProgramCompiler { mut c, mut main } = walker.backend;
+    main.emit(Instr::Halt);
+    c.funcs[0] = main.code;
+    let exports = exports.into_iter().map(|(name, (g, t))| (name, g, t)).collect();
+    Ok(Bytecode { funcs: c.funcs, globals: c.globals as usize, exports })
+}
+
+struct Compiler {
+    funcs: Vec<Vec<Instr>>,
+    globals: u32,
+    // the globals in scope by name, latest last
+    scope: Vec<(Arc<str>, u32)>,
+}
+
+/* A function being compiled. 'depth' is the number of values its code will
+ * have on the stack at the current instruction, counted from the frame's base,
+ * which is how slots are given to locals. */
+#[derive(Default)]
+struct Frame {
+    code: Vec<Instr>,
+    locals: Vec<(Arc<str>, u32)>,
+    captures: Vec<Arc<str>>,
+    depth: u32,
+}
+
+impl Frame {
+    fn emit(&mut self, instr: Instr) {
+        self.depth = (self.depth as i64 + instr.effect()) as u32;
+        self.code.push(instr);
+    }
+
+    // How to load 'name' if it's a local or a capture, rather than a global
+    fn load(&self, name: &str) -> Option<Instr> {
+        if let Some((_, slot)) = self.locals.iter().rev().find(|(n, _)| &**n == name) {
+            return Some(Instr::Local(*slot));
+        }
+        self.captures.iter()
+            .position(|n| &**n == name)
+            .map(|i| Instr::Capture(i as u32))
+    }
+}
+
+impl Compiler {
+    fn new() -> Compiler {
+        // function 0 is filled in once everything else is compiled
+        Compiler { funcs: vec![vec![]], globals: 0, scope: vec![] }
+    }
+
+    fn expr(&mut self, f: &mut Frame, e: &TypedExpression) -> Result<(), CompileError> {
+        if e.typ.has_error() {
+            return Err(CompileError::IllTyped);
+        }
+
+        match &e.kind {
+            TypedKind::Var { name } => {
+                let instr = f.load(name)
+                    .or_else(|| self.scope.iter().rev().find(|(n, _)| n == name).map(|(_, g)| Instr::Global(*g)))
+                    .ok_or_else(|| CompileError::Unbound { name: name.clone() })?;
+                f.emit(instr);
+            }
+            TypedKind::App { f: func, e: arg } => {
+                self.expr(f, func)?;
+                self.expr(f, arg)?;
+                f.emit(Instr::Call);
+            }
+
+            /* The lambda's free variables that are locals or captures here get
+             * copied into its closure. Globals can be loaded from anywhere, so
+             * they aren't captured. */
+            TypedKind::Abs { name, e: body } => {
+                let mut free = vec![];
+                free_vars(e, &mut vec![], &mut free);
+                let captures = free.into_iter()
+                    .filter(|v| f.load(v).is_some())
+                    .collect::<Vec<_>>();
+                for v in &captures {
+                    f.emit(f.load(v).unwrap());
+                }
+
+                let mut inner = Frame { locals: vec![(name.clone(), 0)], captures, depth: 1, ..Frame::default() };
+                self.expr(&mut inner, body)?;
+                inner.emit(Instr::Return);
+
+                let func = self.funcs.len() as u32;
+                self.funcs.push(inner.code);
+                f.emit(Instr::Closure { func, captures: inner.captures.len() as u32 });
+            }
+
+            /* The value of the let stays on the stack while the body runs,
+             * which makes it a local. */
+            TypedKind::Let { name, e0, e1, .. } => {
+                self.expr(f, e0)?;
+                f.locals.push((name.clone(), f.depth - 1));
+                self.expr(f, e1)?;
+                f.locals.pop();
+                f.emit(Instr::Slide(1));
+            }
+            TypedKind::True => f.emit(Instr::Bool(true)),
+            TypedKind::False => f.emit(Instr::Bool(false)),
+            TypedKind::Error => return Err(CompileError::IllTyped),
+        }
+        Ok(())
+    }
+}
+
+/* The declarations of a program, each of which is compiled into 'main' and
+ * stored in a global of its own */
+struct ProgramCompiler {
+    c: Compiler,
+    main: Frame,
+}
+
+impl Backend for ProgramCompiler {
+    type Export = (u32, Arc<Type>);
+    type Error = CompileError;
+    type Scope = Vec<(Arc<str>, u32)>;
+
+    fn define(&mut self, _: &Arc<str>, _: &Expression, typed: Option<&TypedExpression>) -> Result<(u32, Arc<Type>), CompileError> {
+        let e = typed.expect("every let has a typed AST");
+        self.c.expr(&mut self.main, e)?;
+        let g = self.c.globals;
+        self.c.globals += 1;
+        self.main.emit(Instr::SetGlobal(g));
+        Ok((g, e.typ.clone()))
+    }
+
+    fn bind(&mut self, name: Arc<str>, (g, _): (u32, Arc<Type>)) {
+        self.c.scope.push((name, g));
+    }
+
+    fn scope(&self) -> Self::Scope {
+        self.c.scope.clone()
+    }
+
+    fn set_scope(&mut self, scope: Self::Scope) {
+        self.c.scope = scope;
+    }
+}
+
+/* The variables that 'e' uses without binding them, in order of first use */
+fn free_vars(e: &TypedExpression, bound: &mut Vec<Arc<str>>, free: &mut Vec<Arc<str>>) {
+    match &e.kind {
+        TypedKind::Var { name } => {
+            if !bound.contains(name) && !free.contains(name) {
+                free.push(name.clone());
+            }
+        }
+        TypedKind::App { f, e } => {
+            free_vars(f, bound, free);
+            free_vars(e, bound, free);
+        }
+        TypedKind::Abs { name, e } => {
+            bound.push(name.clone());
+            free_vars(e, bound, free);
+            bound.pop();
+        }
+        TypedKind::Let { name, e0, e1, .. } => {
+            free_vars(e0, bound, free);
+            bound.push(name.clone());
+            free_vars(e1, bound, free);
+            bound.pop();
+        }
+        TypedKind::True | TypedKind::False | TypedKind::Error => {}
+    }
+}
+
+/// The values of a program's exports along with their names, see
+/// Bytecode::exports.
+pub type Values = Vec<(Arc<str>, Value)>;
+
+struct Closure {
+    func: usize,
+    captures: Box<[Word]>,
+}
+
+// Where to carry on once a call returns
+struct Return {
+    func: usize,
+    pc: usize,
+    base: usize,
+    closure: usize,
+}
+
+/// Runs a compiled program and reads back the values of its exports.
+pub fn run(code: &Bytecode) -> Values {
+    let mut stack: Vec<Word> = vec![];
+    let mut frames: Vec<Return> = vec![];
+    let mut heap: Vec<Closure> = vec![];
+    let mut globals: Vec<Word> = vec![0; code.globals];
+
+    // the top-level code isn't in a closure, so it has no captures to load
+    let (mut func, mut pc, mut base, mut closure) = (0, 0, 0, usize::MAX);
+    let pop = |stack: &mut Vec<Word>| stack.pop().expect("the compiler keeps the stack balanced");
+    loop {
+        let instr = code.funcs[func][pc];
+        pc += 1;
+        match instr {
+            Instr::Bool(b) => stack.push(b as Word),
+            Instr::Local(i) => stack.push(stack[base + i as usize]),
+            Instr::Capture(i) => stack.push(heap[closure].captures[i as usize]),
+            Instr::Global(i) => stack.push(globals[i as usize]),
+            Instr::SetGlobal(i) => globals[i as usize] = pop(&mut stack),
+            Instr::Closure { func, captures } => {
+                let captures = stack.split_off(stack.len() - captures as usize).into();
+                heap.push(Closure { func: func as usize, captures });
+                stack.push(heap.len() - 1);
+            }
+            Instr::Call => {
+                let arg = pop(&mut stack);
+                let callee = pop(&mut stack);
+                frames.push(Return { func, pc, base, closure });
+                (func, pc, base, closure) = (heap[callee].func, 0, stack.len(), callee);
+                stack.push(arg);
+            }
+            Instr::Return => {
+                let result = pop(&mut stack);
+                stack.truncate(base);
+                let ret = frames.pop().expect("function 0 halts instead of returning");
+                (func, pc, base, closure) = (ret.func, ret.pc, ret.base, ret.closure);
+                stack.push(result);
+            }
+            Instr::Slide(n) => {
+                let top = pop(&mut stack);
+                stack.truncate(stack.len() - n as usize);
+                stack.push(top);
+            }
+            Instr::Halt => break,
+        }
+    }
+
+    /* A closed, well-typed value whose type is a variable can't be written,
+     * so anything that isn't a Bool is a function. */
+    code.exports.iter()
+        .map(|(name, g, t)| {
+            let word = globals[*g as usize];
+            let value = match **t {
+                Type::Bool => Value::Bool(word != 0),
+                _ => Value::Function,
+            };
+            (name.clone(), value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval;
+    use crate::eval::tests::{Rng, gen_expr};
+    use crate::typck::infer_typed;
+
+    fn run_str(src: &str) -> String {
+        let (e, errors) = infer_typed(ast::parse(src).unwrap());
+        assert!(errors.is_empty());
+        run(&compile_expr(&e).unwrap())[0].1.to_string()
+    }
+
+    #[test]
+    fn test_compile() {
+        let (e, _) = infer_typed(ast::parse(r"let k = \x. \y. x in k true false").unwrap());
+        let code = compile_expr(&e).unwrap();
+        assert_eq!(code.to_string(), "\
+fn 0:
+  closure 2 0
+  local 0
+  bool true
+  call
+  bool false
+  call
+  slide 1
+  set_global 0
+  halt
+fn 1:
+  capture 0
+  return
+fn 2:
+  local 0
+  closure 1 1
+  return
+");
+        assert_eq!(run(&code), vec![("it".into(), Value::Bool(true))]);
+
+        let (e, _) = infer_typed(ast::parse("true true").unwrap());
+        assert!(matches!(compile_expr(&e), Err(CompileError::IllTyped)));
+    }
+
+    #[test]
+    fn test_run() {
+        let not = r"\b. \t. \f. b f t";
+        let three = r"\f. \x. f (f (f x))";
+        assert_eq!(run_str(&format!(r"let not = {not} in ({three}) not (\t. \f. t) true false")), "false");
+        assert_eq!(run_str(r"let x = false in let f = \y. x in let x = true in f x"), "false");
+        assert_eq!(run_str(r"\x. x"), "<function>");
+
+        let program = ast::parse_program("let id = \\x. x\nmodule M = struct\n  let b = id true\nend\nlet c = M.b").unwrap();
+        let mut loader = ModuleLoader::default();
+        let typed = crate::infer_program_typed(&mut loader, &program);
+        let code = compile_program(&mut loader, &program, &typed).unwrap();
+        let values = run(&code).iter().map(|(n, v)| format!("{n} = {v}")).collect::<Vec<_>>();
+        assert_eq!(values, ["id = <function>", "M.b = true", "c = true"]);
+    }
+
+    #[test]
+    fn test_same_as_eval() {
+        let mut rng = Rng(0x2545f4914f6cdd1d);
+        for _ in 0..5000 {
+            let e = gen_expr(&mut rng, 6, &mut vec![]);
+            let (typed, errors) = infer_typed(e.clone());
+            if errors.is_empty() {
+                let expected = eval::eval(&e).unwrap().to_string();
+                let actual = run(&compile_expr(&typed).unwrap())[0].1.to_string();
+                assert_eq!(actual, expected, "{e} ran differently on the machine");
+            }
+        }
+    }
+}
@@ -0,0 +1,156 @@
+// Runs the subcommands the way a build script would.
+
+use algorithm_j::ser::Json;
+
+use std::fs;
+use std::io::Write;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+fn run(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_algorithm-j"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn text(bytes: &[u8]) -> &str {
+    std::str::from_utf8(bytes).unwrap()
+}
+
+// A directory of source files for one test, removed again when the test is done
+struct Project(PathBuf);
+
+impl Deref for Project {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for Project {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn project(test: &str) -> Project {
+    let root = Project(std::env::temp_dir().join(format!("algorithm-j-cli-{test}-{}", std::process::id())));
+    fs::create_dir_all(root.join("lib")).unwrap();
+    fs::write(root.join("main.lam"), "import Util\nlet a = Util.id true\nlet b = \\x. x x\n").unwrap();
+    fs::write(root.join("ok.lam"), "import Util\nlet k = \\x. \\y. Util.id x\n").unwrap();
+    fs::write(root.join("lib/Util.lam"), "let id = \\x. x\n").unwrap();
+    root
+}
+
+#[test]
+fn test_check() {
+    let root = project("check");
+    let (main, ok) = (root.join("main.lam"), root.join("ok.lam"));
+
+    let out = run(&["check", "-I", root.join("lib").to_str().unwrap(), ok.to_str().unwrap()], "");
+    assert_eq!(out.status.code(), Some(0));
+    assert_eq!(text(&out.stdout), "");
+    assert_eq!(text(&out.stderr), "");
+
+    let out = run(&["check", "--include", root.join("lib").to_str().unwrap(), main.to_str().unwrap()], "");
+    assert_eq!(out.status.code(), Some(1));
+    let expected = format!("{}:3:13: Recursive types are not allowed: 'a ~ 'a -> 'b, arising from 'x x'.\n", main.display());
+    assert_eq!(text(&out.stderr), expected);
+
+    // without the include the import fails, at the import
+    let out = run(&["check", main.to_str().unwrap()], "");
+    assert_eq!(out.status.code(), Some(1));
+    assert!(text(&out.stderr).starts_with(&format!("{}:1:1: Module Util not found", main.display())));
+
+    let out = run(&["check", root.join("missing.lam").to_str().unwrap()], "");
+    assert_eq!(out.status.code(), Some(2));
+
+    let out = run(&["check", "--format", "yaml"], "");
+    assert_eq!(out.status.code(), Some(2));
+    assert!(text(&out.stderr).starts_with("Unknown format 'yaml', expecting human or json."));
+}
+
+#[test]
+fn test_infer() {
+    let out = run(&["infer"], "let id = \\x. x\nlet b = id true\n");
+    assert_eq!(out.status.code(), Some(0));
+    assert_eq!(text(&out.stdout), "id : forall a. a -> a\nb : Bool\n");
+
+    let out = run(&["infer", "--format=json", "-"], "let b = true\nlet c = b b\nlet d = (");
+    assert_eq!(out.status.code(), Some(1));
+    let json = text(&out.stdout).parse::<Json>().unwrap();
+    assert_eq!(json.get("file").and_then(Json::as_str), Some("<stdin>"));
+
+    let items = json.get("items").and_then(Json::as_array).unwrap();
+    let names = items.iter().map(|i| i.get("name").and_then(Json::as_str).unwrap()).collect::<Vec<_>>();
+    assert_eq!(names, ["b", "c", "d"]);
+    assert_eq!(items[0].get("type").unwrap().to_string(), r#"{"kind":"forall","vars":[],"type":{"kind":"bool"}}"#);
+
+    let errors = json.get("errors").and_then(Json::as_array).unwrap();
+    let at = |i: usize| (errors[i].get("line").and_then(Json::as_u64), errors[i].get("column").and_then(Json::as_u64));
+    assert_eq!(errors.len(), 2);
+    assert_eq!(at(0), (Some(3), Some(10)));
+    assert_eq!(at(1), (Some(2), Some(9)));
+    assert_eq!(errors[1].get("error").and_then(|e| e.get("kind")).and_then(Json::as_str), Some("type_mismatch"));
+}
+
+#[test]
+fn test_eval() {
+    let root = project("eval");
+    let out = run(&["eval", "-I", root.join("lib").to_str().unwrap(), "-"], "import Util\nlet k = \\x. \\y. x\nlet a = k false (Util.id true)\n");
+    assert_eq!(out.status.code(), Some(0));
+    assert_eq!(text(&out.stdout), "k = <function>\na = false\n");
+
+    let out = run(&["eval", "--vm", "-I", root.join("lib").to_str().unwrap(), root.join("ok.lam").to_str().unwrap()], "");
+    assert_eq!(out.status.code(), Some(0));
+    assert_eq!(text(&out.stdout), "k = <function>\n");
+
+    // programs that don't type check aren't run
+    let out = run(&["eval", "--format", "json"], "let a = true\nlet b = a a\n");
+    assert_eq!(out.status.code(), Some(1));
+    let json = text(&out.stdout).parse::<Json>().unwrap();
+    assert_eq!(json.get("items").and_then(Json::as_array).map(<[Json]>::len), Some(0));
+    assert_eq!(json.get("errors").and_then(Json::as_array).map(<[Json]>::len), Some(1));
+}
+
+#[test]
+fn test_js() {
+    let root = project("js");
+    let include = root.join("lib");
+    let src = "import Util\nmodule M = struct\n  let not = \\b. \\t. \\f. b f t\nend\nlet a = M.not (\\t. \\f. t) false (Util.id true)\n";
+    let out = run(&["js", "-I", include.to_str().unwrap()], src);
+    assert_eq!(out.status.code(), Some(0));
+    assert!(text(&out.stdout).contains("// import Util\n// id : forall a. a -> a\nconst id = (x) => x;\n"));
+
+    let out = run(&["js", "--format", "json"], "let b = \\x. x x\n");
+    assert_eq!(out.status.code(), Some(1));
+    let json = text(&out.stdout).parse::<Json>().unwrap();
+    assert_eq!(json.get("code"), Some(&Json::Null));
+}
+
+// run with cargo test -- --ignored, which needs node to be installed
+#[test]
+#[ignore = "needs node"]
+fn test_js_node() {
+    let root = project("js-node");
+    let include = root.join("lib");
+    let src = "import Util\nmodule M = struct\n  let not = \\b. \\t. \\f. b f t\nend\nlet a = M.not (\\t. \\f. t) false (Util.id true)\n";
+    let out = run(&["js", "-I", include.to_str().unwrap()], src);
+    assert_eq!(out.status.code(), Some(0));
+
+    // node prints the same as eval does
+    let script = root.join("a.js");
+    fs::write(&script, &out.stdout).unwrap();
+    let evaluated = run(&["eval", "-I", include.to_str().unwrap()], src);
+    let node = Command::new("node").arg(&script).output()
+        .unwrap_or_else(|e| panic!("couldn't run node, which this test needs: {e}"));
+    assert_eq!(text(&node.stdout), text(&evaluated.stdout));
+}
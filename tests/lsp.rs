@@ -0,0 +1,73 @@
+// Drives the language server binary the way an editor would, over its stdin and stdout.
+
+use algorithm_j::lsp;
+use algorithm_j::ser::Json;
+
+use std::io::{BufReader, Write};
+use std::process::{Command, Stdio};
+
+fn message(body: &str) -> String {
+    format!("Content-Length: {}\r\n\r\n{body}", body.len())
+}
+
+#[test]
+fn test_session() {
+    let uri = "file:///nonexistent/main.lam";
+    let text = r"let id = \\x. x\nlet a = id true\nlet b = \\y. let z = y in z a\nlet c = ida\n";
+    let requests = [
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"capabilities":{}}}"#.to_string(),
+        r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#.to_string(),
+        format!(r#"{{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{{"textDocument":{{"uri":"{uri}","languageId":"lam","version":1,"text":"{text}"}}}}}}"#),
+        // the `id` in `let a = id true`
+        format!(r#"{{"jsonrpc":"2.0","id":2,"method":"textDocument/hover","params":{{"textDocument":{{"uri":"{uri}"}},"position":{{"line":1,"character":9}}}}}}"#),
+        // the binder z in `let z = y`
+        format!(r#"{{"jsonrpc":"2.0","id":3,"method":"textDocument/hover","params":{{"textDocument":{{"uri":"{uri}"}},"position":{{"line":2,"character":16}}}}}}"#),
+        // the z in `in z a`
+        format!(r#"{{"jsonrpc":"2.0","id":4,"method":"textDocument/definition","params":{{"textDocument":{{"uri":"{uri}"}},"position":{{"line":2,"character":25}}}}}}"#),
+        format!(r#"{{"jsonrpc":"2.0","id":5,"method":"textDocument/definition","params":{{"textDocument":{{"uri":"{uri}"}},"position":{{"line":1,"character":9}}}}}}"#),
+        r#"{"jsonrpc":"2.0","id":6,"method":"shutdown"}"#.to_string(),
+        r#"{"jsonrpc":"2.0","method":"exit"}"#.to_string(),
+    ];
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lsp"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let input = requests.iter().map(|r| message(r)).collect::<String>();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+
+    let mut output = BufReader::new(child.stdout.take().unwrap());
+    let mut responses = vec![];
+    while let Some(body) = lsp::read_message(&mut output).unwrap() {
+        responses.push(body.parse::<Json>().unwrap());
+    }
+    assert!(child.wait().unwrap().success());
+
+    let result = |id: u64| {
+        responses.iter()
+            .find(|r| r.get("id").and_then(Json::as_u64) == Some(id))
+            .and_then(|r| r.get("result"))
+            .unwrap_or_else(|| panic!("no response to request {id}"))
+    };
+    let hover = |id| result(id).get("contents").and_then(|c| c.get("value")).and_then(Json::as_str).unwrap();
+    let range = |r: &Json| r.get("range").unwrap().to_string();
+
+    assert_eq!(result(1).get("capabilities").unwrap().get("hoverProvider"), Some(&Json::Bool(true)));
+    assert_eq!(hover(2), "id : Bool -> Bool");
+    // z is only as general as y, which z a makes a function of Bool
+    assert_eq!(hover(3), "z : Bool -> 'a");
+    assert_eq!(range(result(4)), r#"{"start":{"line":2,"character":16},"end":{"line":2,"character":17}}"#);
+    assert_eq!(range(result(5)), r#"{"start":{"line":0,"character":4},"end":{"line":0,"character":6}}"#);
+    assert_eq!(result(6), &Json::Null);
+
+    let diagnostics = responses.iter()
+        .find(|r| r.get("method").and_then(Json::as_str) == Some("textDocument/publishDiagnostics"))
+        .and_then(|r| r.get("params"))
+        .and_then(|p| p.get("diagnostics"))
+        .and_then(Json::as_array)
+        .unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].get("message").and_then(Json::as_str), Some("Variable ida not found. Did you mean id?"));
+    assert_eq!(range(&diagnostics[0]), r#"{"start":{"line":3,"character":8},"end":{"line":3,"character":11}}"#);
+}